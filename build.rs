@@ -0,0 +1,142 @@
+// Generates the built-in table of IANA Information Elements from the IANA registry in
+// data/ipfix.xml, along with the functions that parse data types, semantics and statuses by
+// their IANA names. Both end up in $OUT_DIR/iana.rs and are included by src/registry.rs.
+
+extern crate xml;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+#[path = "src/iana_xml.rs"]
+mod iana_xml;
+
+const REGISTRY: &str = "data/ipfix.xml";
+
+const DATA_TYPES: &[(&str, &str)] = &[("octetArray", "OctetArray"),
+                                      ("unsigned8", "Unsigned8"),
+                                      ("unsigned16", "Unsigned16"),
+                                      ("unsigned32", "Unsigned32"),
+                                      ("unsigned64", "Unsigned64"),
+                                      ("signed8", "Signed8"),
+                                      ("signed16", "Signed16"),
+                                      ("signed32", "Signed32"),
+                                      ("signed64", "Signed64"),
+                                      ("float32", "Float32"),
+                                      ("float64", "Float64"),
+                                      ("boolean", "Boolean"),
+                                      ("macAddress", "MacAddress"),
+                                      ("string", "String"),
+                                      ("dateTimeSeconds", "DateTimeSeconds"),
+                                      ("dateTimeMilliseconds", "DateTimeMilliseconds"),
+                                      ("dateTimeMicroseconds", "DateTimeMicroseconds"),
+                                      ("dateTimeNanoseconds", "DateTimeNanoseconds"),
+                                      ("ipv4Address", "Ipv4Address"),
+                                      ("ipv6Address", "Ipv6Address"),
+                                      ("basicList", "BasicList"),
+                                      ("subTemplateList", "SubTemplateList"),
+                                      ("subTemplateMultiList", "SubTemplateMultiList")];
+
+const SEMANTICS: &[(&str, &str)] = &[("default", "Default"),
+                                     ("quantity", "Quantity"),
+                                     ("totalCounter", "TotalCounter"),
+                                     ("deltaCounter", "DeltaCounter"),
+                                     ("identifier", "Identifier"),
+                                     ("flags", "Flags"),
+                                     ("list", "List"),
+                                     ("snmpCounter", "SnmpCounter"),
+                                     ("snmpGauge", "SnmpGauge")];
+
+const STATUSES: &[(&str, &str)] = &[("current", "Current"), ("deprecated", "Deprecated")];
+
+// The variant for an IANA name. Values the tables above don't know yet get the first variant of
+// the table, with a warning, so that a newer registry still builds.
+fn lookup(table: &[(&str, &'static str)], value: &str, what: &str, name: &str) -> &'static str {
+    match table.iter().find(|&&(iana, _)| iana == value) {
+        Some(&(_, variant)) => variant,
+        None => {
+            println!("cargo:warning={}: unknown {} {:?} for {}, using {}",
+                     REGISTRY,
+                     what,
+                     value,
+                     name,
+                     table[0].1);
+            table[0].1
+        }
+    }
+}
+
+// fn <name>(name: &str) -> Option<<type>>, matching the IANA names of `table`
+fn write_parse_fn<W: Write>(out: &mut W,
+                            name: &str,
+                            type_name: &str,
+                            table: &[(&str, &str)])
+                            -> std::io::Result<()> {
+    writeln!(out, "fn {}(name: &str) -> Option<{}> {{", name, type_name)?;
+    writeln!(out, "    match name {{")?;
+    for &(iana, variant) in table {
+        writeln!(out, "        {:?} => Some({}::{}),", iana, type_name, variant)?;
+    }
+    writeln!(out, "        _ => None,")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/iana_xml.rs");
+    println!("cargo:rerun-if-changed={}", REGISTRY);
+
+    let file = File::open(REGISTRY).expect("cannot open the IANA registry");
+    let records = iana_xml::element_records(BufReader::new(file))
+        .expect("cannot parse the IANA registry");
+    let mut rows = BTreeMap::<u16, String>::new();
+    for record in &records {
+        if let Some((id, row)) = element(record) {
+            if rows.insert(id, row).is_some() {
+                panic!("{}: element {} is defined twice", REGISTRY, id);
+            }
+        }
+    }
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("iana.rs");
+    let mut out = File::create(path).unwrap();
+    write_parse_fn(&mut out, "parse_data_type", "DataType", DATA_TYPES).unwrap();
+    write_parse_fn(&mut out, "parse_semantics", "Semantics", SEMANTICS).unwrap();
+    write_parse_fn(&mut out, "parse_status", "Status", STATUSES).unwrap();
+    writeln!(out, "const IANA_ELEMENTS: &[IanaElement] = &[").unwrap();
+    for row in rows.values() {
+        writeln!(out, "    {},", row).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
+
+// The table row for one <record>, or None for records that don't define a single element, such
+// as the ranges reserved for NetFlow v9 compatibility.
+fn element(record: &iana_xml::Record) -> Option<(u16, String)> {
+    let field = |name: &str| record.get(name).map(String::as_str).filter(|value| !value.is_empty());
+
+    let id = field("elementId")?.parse::<u16>().ok()?;
+    let name = field("name")?;
+    let data_type = lookup(DATA_TYPES, field("dataType")?, "data type", name);
+    let semantics = lookup(SEMANTICS,
+                           field("dataTypeSemantics").unwrap_or("default"),
+                           "data type semantics",
+                           name);
+    let status = lookup(STATUSES, field("status").unwrap_or("current"), "status", name);
+    let units = match field("units") {
+        Some(units) => format!("Some({:?})", units),
+        None => "None".into(),
+    };
+
+    Some((id,
+          format!("({}, {:?}, DataType::{}, Semantics::{}, {}, Status::{})",
+                  id,
+                  name,
+                  data_type,
+                  semantics,
+                  units,
+                  status)))
+}
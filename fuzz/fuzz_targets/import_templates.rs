@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ipfix::IpfixConsumer;
+
+// Loads the input as a template snapshot. Whatever loads has to export to a snapshot that loads
+// again.
+fuzz_target!(|data: &[u8]| {
+    let mut consumer = IpfixConsumer::new();
+    if consumer.import_templates(data).is_ok() {
+        let mut snapshot = Vec::new();
+        consumer.export_templates(&mut snapshot).unwrap();
+        IpfixConsumer::new().import_templates(&snapshot[..]).unwrap();
+    }
+});
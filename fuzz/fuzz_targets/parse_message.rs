@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ipfix::{get_message_length, is_template_set, IpfixConsumer, IpfixPrinter};
+
+// Treats the input as a stream of IPFIX messages, so that templates defined by one message are
+// used to decode the data sets of the ones after it. Crashes found here should be minimized and
+// added to tests/corpus so they are replayed by `cargo test`.
+fuzz_target!(|data: &[u8]| {
+    let mut consumer = IpfixConsumer::new();
+    let printer = IpfixPrinter::new();
+
+    let _ = is_template_set(data);
+
+    let mut remaining = data;
+    while let Ok(length) = get_message_length(remaining) {
+        // a zero length would never advance, so hand the rest over as a single message
+        let length = if length == 0 { remaining.len() } else { (length as usize).min(remaining.len()) };
+        let (message, rest) = remaining.split_at(length);
+        if let Ok(parsed) = consumer.parse_message(0u64, message) {
+            for dataset in parsed.sets {
+                printer.print_json(dataset);
+            }
+        }
+        remaining = rest;
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ipfix::{IpfixPrinter, SflowDecoder};
+
+// Decodes the input as one sFlow v5 datagram, and prints the flow samples it holds.
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = SflowDecoder::new();
+    if let Ok(datagram) = decoder.parse_datagram(data) {
+        IpfixPrinter::new().print_json(datagram.flows);
+    }
+});
@@ -0,0 +1,31 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ipfix::{IpfixConsumer, IpfixPrinter, StreamDecoder};
+
+// Feeds the input to a StreamDecoder in chunks, the first byte giving their size, so that
+// messages get split across pushes the way TCP reads split them.
+fuzz_target!(|data: &[u8]| {
+    let (chunk_size, data) = match data.split_first() {
+        Some((&chunk_size, data)) => (chunk_size.max(1) as usize, data),
+        None => return,
+    };
+    let mut decoder = StreamDecoder::new(0u64);
+    let mut consumer = IpfixConsumer::new();
+    consumer.open_stream(0u64);
+    let printer = IpfixPrinter::new();
+
+    for chunk in data.chunks(chunk_size) {
+        decoder.push(chunk);
+        while let Ok(Some(frame)) = decoder.next_frame() {
+            if let Ok(parsed) = consumer.parse_message(0u64, frame) {
+                for dataset in parsed.sets {
+                    printer.print_json(dataset);
+                }
+            }
+        }
+        if decoder.is_failed() {
+            break;
+        }
+    }
+});
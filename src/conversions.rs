@@ -1,18 +1,37 @@
-use std;
-
 // Conversions used internally to parse IPFIX headers and specifiers
+// These return None instead of panicking when the buffer is too short, since the bytes come
+// straight off the wire
+
+#[inline]
+pub fn be_buf_to_u16(s: &[u8]) -> Option<u16> {
+    if s.len() < 2 {
+        return None;
+    }
+    Some(u16::from_be_bytes([s[0], s[1]]))
+}
 
 #[inline]
-pub fn be_buf_to_u16(s: &[u8]) -> u16 {
-    unsafe { std::mem::transmute([s[1], s[0]]) }
+pub fn be_buf_to_u32(s: &[u8]) -> Option<u32> {
+    if s.len() < 4 {
+        return None;
+    }
+    Some(u32::from_be_bytes([s[0], s[1], s[2], s[3]]))
 }
 
 #[inline]
-pub fn be_buf_to_u32(s: &[u8]) -> u32 {
-    unsafe { std::mem::transmute([s[3], s[2], s[1], s[0]]) }
+pub fn be_buf_to_u64(s: &[u8]) -> Option<u64> {
+    if s.len() < 8 {
+        return None;
+    }
+    Some(u64::from_be_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]))
 }
 
+// Reduced-size encoding (RFC 7011 §6.2) lets an exporter send an integer in fewer bytes than its
+// type, down to a single one
 #[inline]
-pub fn be_buf_to_u64(s: &[u8]) -> u64 {
-    unsafe { std::mem::transmute([s[7], s[6], s[5], s[4], s[3], s[2], s[1], s[0]]) }
+pub fn be_buf_to_uint(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 8 {
+        return None;
+    }
+    Some(s.iter().fold(0, |value, &byte| value << 8 | byte as u64))
 }
@@ -0,0 +1,269 @@
+use std::error::Error;
+use std::fmt;
+
+use super::registry::DataType;
+
+// Errors raised while parsing an IPFIX message. Every variant records the byte offset into the
+// message where the problem was found, and the set id of the enclosing set when there is one.
+// Problems in the message header itself have no enclosing set.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpfixError {
+    /// The message header carries a version number other than 10.
+    BadVersion {
+        offset: usize,
+        set_id: Option<u16>,
+        version: u16,
+    },
+    /// The message is too short to hold its header: `length` bytes are needed, and only
+    /// `available` were received.
+    TruncatedHeader {
+        offset: usize,
+        set_id: Option<u16>,
+        length: usize,
+        available: usize,
+    },
+    /// The length in the message header does not match the number of bytes received.
+    LengthMismatch {
+        offset: usize,
+        set_id: Option<u16>,
+        expected: usize,
+        actual: usize,
+    },
+    /// A set claims more bytes than remain in the message.
+    TruncatedSet {
+        offset: usize,
+        set_id: Option<u16>,
+        length: usize,
+        available: usize,
+    },
+    /// A set header carries a length below the 4 bytes of the header itself.
+    ShortSetLength {
+        offset: usize,
+        set_id: Option<u16>,
+        length: u16,
+    },
+    /// A data set references a template that has not been received (yet).
+    UnknownTemplate { offset: usize, set_id: Option<u16> },
+    /// A template record ends in the middle of a field specifier, or an options template record
+    /// has no scope fields. The offset is that of the field specifier, or of the scope field
+    /// count.
+    MalformedFieldSpecifier { offset: usize, set_id: Option<u16> },
+    /// The message header gives a length shorter than the header itself, or longer than a
+    /// `StreamDecoder` accepts. Nothing after it in a stream can be split into messages. Also
+    /// raised for NetFlow v5 packets too long for the length of a message header.
+    InvalidMessageLength {
+        offset: usize,
+        set_id: Option<u16>,
+        length: usize,
+    },
+    /// A template id was reused for a different template without withdrawing it first, which
+    /// RFC 7011 §8.1 forbids on stream sessions. The session should be closed.
+    TemplateRedefined {
+        offset: usize,
+        set_id: Option<u16>,
+        template_id: u16,
+    },
+}
+
+impl IpfixError {
+    /// Byte offset into the message where the error was detected.
+    pub fn offset(&self) -> usize {
+        match *self {
+            IpfixError::BadVersion { offset, .. } |
+            IpfixError::TruncatedHeader { offset, .. } |
+            IpfixError::LengthMismatch { offset, .. } |
+            IpfixError::TruncatedSet { offset, .. } |
+            IpfixError::ShortSetLength { offset, .. } |
+            IpfixError::UnknownTemplate { offset, .. } |
+            IpfixError::MalformedFieldSpecifier { offset, .. } |
+            IpfixError::InvalidMessageLength { offset, .. } |
+            IpfixError::TemplateRedefined { offset, .. } => offset,
+        }
+    }
+
+    /// Id of the set the error was found in, or `None` for errors in the message header.
+    pub fn set_id(&self) -> Option<u16> {
+        match *self {
+            IpfixError::BadVersion { set_id, .. } |
+            IpfixError::TruncatedHeader { set_id, .. } |
+            IpfixError::LengthMismatch { set_id, .. } |
+            IpfixError::TruncatedSet { set_id, .. } |
+            IpfixError::ShortSetLength { set_id, .. } |
+            IpfixError::UnknownTemplate { set_id, .. } |
+            IpfixError::MalformedFieldSpecifier { set_id, .. } |
+            IpfixError::InvalidMessageLength { set_id, .. } |
+            IpfixError::TemplateRedefined { set_id, .. } => set_id,
+        }
+    }
+
+    /// True when the message was well-formed but references a template we have not seen, as
+    /// opposed to the exporter sending something invalid.
+    pub fn is_missing_template(&self) -> bool {
+        matches!(*self, IpfixError::UnknownTemplate { .. })
+    }
+}
+
+impl fmt::Display for IpfixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IpfixError::BadVersion { offset, version, .. } => {
+                write!(f, "unsupported version {} at offset {}", version, offset)
+            }
+            IpfixError::TruncatedHeader { length, available, .. } => {
+                write!(f,
+                       "message of {} bytes is too short for its {} byte header",
+                       available,
+                       length)
+            }
+            IpfixError::LengthMismatch { expected, actual, .. } => {
+                write!(f,
+                       "message header length {} does not match {} bytes received",
+                       expected,
+                       actual)
+            }
+            IpfixError::TruncatedSet { offset, length, available, .. } => {
+                write!(f,
+                       "set at offset {} claims {} bytes but only {} remain",
+                       offset,
+                       length,
+                       available)
+            }
+            IpfixError::ShortSetLength { offset, length, .. } => {
+                write!(f, "set at offset {} has invalid length {}", offset, length)
+            }
+            IpfixError::UnknownTemplate { offset, set_id } => {
+                write!(f,
+                       "data set at offset {} references unknown template {}",
+                       offset,
+                       set_id.unwrap_or(0))
+            }
+            IpfixError::MalformedFieldSpecifier { offset, .. } => {
+                write!(f, "malformed field specifier at offset {}", offset)
+            }
+            IpfixError::InvalidMessageLength { length, .. } => {
+                write!(f, "invalid message length {}", length)
+            }
+            IpfixError::TemplateRedefined { offset, template_id, .. } => {
+                write!(f,
+                       "template {} at offset {} redefined without withdrawal",
+                       template_id,
+                       offset)
+            }
+        }
+    }
+}
+
+impl Error for IpfixError {}
+
+/// Raised when a field value can't be decoded as the abstract data type of its Information
+/// Element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueError {
+    /// The value has a length the data type can't be encoded in, reduced-size encoding
+    /// (RFC 7011 §6.2) included.
+    InvalidLength { data_type: DataType, length: usize },
+    /// A boolean other than 1 (true) or 2 (false).
+    InvalidBoolean(u8),
+    /// The value can't be represented in a field of this length, e.g. an integer too large for
+    /// its reduced-size encoding or a time before the epoch of its data type. Also raised for
+    /// received times the platform's `SystemTime` can't represent.
+    OutOfRange { data_type: DataType, length: usize },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueError::InvalidLength { data_type, length } => {
+                write!(f, "{:?} can't be encoded in {} bytes", data_type, length)
+            }
+            ValueError::InvalidBoolean(value) => write!(f, "invalid boolean {}", value),
+            ValueError::OutOfRange { data_type, length } => {
+                write!(f, "{:?} value out of range for {} bytes", data_type, length)
+            }
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+/// Raised by `IpfixExporter` when asked for something it can't encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportError {
+    /// No template with this id was added to the exporter.
+    UnknownTemplate(u16),
+    /// The template has no fields or only zero-length ones, an options template has no scope
+    /// fields, or an element id doesn't fit in the 15 bits a template has for it.
+    InvalidTemplate,
+    /// Every template id from the first one up to 65535 has been handed out.
+    TemplateIdsExhausted,
+    /// A record has a different number of values than its template has fields.
+    FieldCount {
+        template_id: u16,
+        expected: usize,
+        actual: usize,
+    },
+    /// A value can't be encoded in its field, `field` being its index in the template.
+    InvalidValue {
+        template_id: u16,
+        field: usize,
+        error: ValueError,
+    },
+    /// The message would be longer than the 65535 bytes its header can describe.
+    MessageTooLong(usize),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportError::UnknownTemplate(template_id) => {
+                write!(f, "unknown template {}", template_id)
+            }
+            ExportError::InvalidTemplate => write!(f, "template can't be encoded"),
+            ExportError::TemplateIdsExhausted => write!(f, "no template ids left"),
+            ExportError::FieldCount { template_id, expected, actual } => {
+                write!(f,
+                       "template {} has {} fields but the record has {} values",
+                       template_id,
+                       expected,
+                       actual)
+            }
+            ExportError::InvalidValue { template_id, field, error } => {
+                write!(f, "field {} of template {}: {}", field, template_id, error)
+            }
+            ExportError::MessageTooLong(length) => {
+                write!(f, "message of {} bytes is longer than 65535", length)
+            }
+        }
+    }
+}
+
+impl Error for ExportError {}
+
+/// Raised by `SflowDecoder` for datagrams it can't decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SflowError {
+    /// The datagram carries a version other than 5.
+    BadVersion(u32),
+    /// The agent address is neither IPv4 (1) nor IPv6 (2).
+    UnknownAddressType(u32),
+    /// The structure at `offset` runs past the end of the datagram, or of the structure it is
+    /// in.
+    Truncated { offset: usize },
+}
+
+impl fmt::Display for SflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SflowError::BadVersion(version) => write!(f, "unsupported sFlow version {}", version),
+            SflowError::UnknownAddressType(address_type) => {
+                write!(f, "unknown agent address type {}", address_type)
+            }
+            SflowError::Truncated { offset } => {
+                write!(f, "truncated datagram at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for SflowError {}
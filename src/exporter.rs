@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::error::ExportError;
+use super::{FieldSpecifier, FieldValue, IPFIX_HEADER_LENGTH, IPFIX_VERSION,
+            OPTIONS_TEMPLATE_SET_ID, SET_HEADER_LENGTH, TEMPLATE_SET_ID};
+
+const FIRST_TEMPLATE_ID: u16 = 256;
+
+/// Encodes IPFIX messages: hands out template ids, writes template and options template sets,
+/// and encodes data records from typed values.
+///
+/// ```
+/// use ipfix::{FieldSpecifier, FieldValue, IpfixConsumer, IpfixExporter};
+///
+/// let mut exporter = IpfixExporter::new();
+/// let template = exporter.add_template(&[FieldSpecifier::new(1, 8), FieldSpecifier::new(2, 4)])
+///     .unwrap();
+///
+/// let mut message = exporter.message(0);
+/// message.templates()
+///     .record(template, &[FieldValue::Unsigned64(1500), FieldValue::Unsigned64(3)])
+///     .unwrap();
+/// let bytes = message.finish().unwrap();
+///
+/// let mut consumer = IpfixConsumer::new();
+/// let parsed = consumer.parse_message(0, &bytes).unwrap();
+/// assert_eq!(parsed.sets[0].records()[0].get(2), Some(Ok(FieldValue::Unsigned64(3))));
+/// ```
+pub struct IpfixExporter {
+    templates: BTreeMap<u16, ExportTemplate>,
+    next_template_id: Option<u16>,
+    // data records sent so far, per Observation Domain
+    sequence_numbers: HashMap<u32, u32>,
+}
+
+struct ExportTemplate {
+    // None for templates, Some for options templates
+    scope_field_count: Option<u16>,
+    fields: Vec<FieldSpecifier>,
+    // the template record, encoded when the template was added
+    record: Vec<u8>,
+}
+
+/// One message being put together by `IpfixExporter::message`. Sets are written in the order
+/// they are added, with consecutive records of the same template sharing a data set.
+pub struct MessageBuilder<'a> {
+    exporter: &'a mut IpfixExporter,
+    observation_domain_id: u32,
+    export_time: Option<u32>,
+    // (set id, set contents after the set header)
+    sets: Vec<(u16, Vec<u8>)>,
+    records: u32,
+}
+
+impl Default for IpfixExporter {
+    fn default() -> IpfixExporter {
+        IpfixExporter::new()
+    }
+}
+
+impl IpfixExporter {
+    pub fn new() -> IpfixExporter {
+        IpfixExporter {
+            templates: BTreeMap::new(),
+            next_template_id: Some(FIRST_TEMPLATE_ID),
+            sequence_numbers: HashMap::new(),
+        }
+    }
+
+    /// Hand out template ids starting from `template_id` instead of 256, e.g. to keep clear of
+    /// the ids another exporter in the same Observation Domain uses.
+    pub fn with_first_template_id(mut self, template_id: u16) -> IpfixExporter {
+        self.next_template_id = Some(template_id.max(FIRST_TEMPLATE_ID));
+        self
+    }
+
+    /// Add a template and return its id.
+    pub fn add_template(&mut self, fields: &[FieldSpecifier]) -> Result<u16, ExportError> {
+        self.add(None, fields.to_vec())
+    }
+
+    /// Add an options template, whose records start with the values of `scope_fields`, and
+    /// return its id.
+    pub fn add_options_template(&mut self,
+                                scope_fields: &[FieldSpecifier],
+                                fields: &[FieldSpecifier])
+                                -> Result<u16, ExportError> {
+        if scope_fields.is_empty() {
+            return Err(ExportError::InvalidTemplate);
+        }
+        let all_fields = scope_fields.iter().chain(fields).cloned().collect();
+        self.add(Some(scope_fields.len() as u16), all_fields)
+    }
+
+    fn add(&mut self,
+           scope_field_count: Option<u16>,
+           fields: Vec<FieldSpecifier>)
+           -> Result<u16, ExportError> {
+        // a field count of 0 would withdraw the template instead, and records of zero-length
+        // fields only can't be told apart from padding
+        if fields.is_empty() || fields.len() > u16::MAX as usize ||
+           fields.iter().all(|field| field.field_length == 0) {
+            return Err(ExportError::InvalidTemplate);
+        }
+        let template_id = self.next_template_id.ok_or(ExportError::TemplateIdsExhausted)?;
+        let mut record = Vec::new();
+        write_template_record(&mut record, template_id, scope_field_count, &fields)
+            .map_err(|_| ExportError::InvalidTemplate)?;
+        self.next_template_id = template_id.checked_add(1);
+        self.templates.insert(template_id,
+                              ExportTemplate {
+                                  scope_field_count,
+                                  fields,
+                                  record,
+                              });
+        Ok(template_id)
+    }
+
+    /// Start a message for an Observation Domain.
+    pub fn message(&mut self, observation_domain_id: u32) -> MessageBuilder<'_> {
+        MessageBuilder {
+            exporter: self,
+            observation_domain_id,
+            export_time: None,
+            sets: Vec::new(),
+            records: 0,
+        }
+    }
+}
+
+impl<'a> MessageBuilder<'a> {
+    /// Export time for the message header, in seconds since the UNIX epoch. Defaults to the
+    /// time `finish` is called.
+    pub fn export_time(&mut self, export_time: u32) -> &mut MessageBuilder<'a> {
+        self.export_time = Some(export_time);
+        self
+    }
+
+    /// Add every template and options template of the exporter. Over UDP these need resending
+    /// periodically, as collectors expire them (RFC 7011 §8.4).
+    pub fn templates(&mut self) -> &mut MessageBuilder<'a> {
+        let ids = self.exporter.templates.keys().cloned().collect::<Vec<_>>();
+        for template_id in ids {
+            self.write_template(template_id);
+        }
+        self
+    }
+
+    /// Add one template or options template of the exporter.
+    pub fn template(&mut self, template_id: u16) -> Result<&mut MessageBuilder<'a>, ExportError> {
+        if !self.exporter.templates.contains_key(&template_id) {
+            return Err(ExportError::UnknownTemplate(template_id));
+        }
+        self.write_template(template_id);
+        Ok(self)
+    }
+
+    /// Add a data record, with one value for each field of the template in template order,
+    /// scope fields first for an options template.
+    pub fn record(&mut self,
+                  template_id: u16,
+                  values: &[FieldValue])
+                  -> Result<&mut MessageBuilder<'a>, ExportError> {
+        let template = self.exporter
+            .templates
+            .get(&template_id)
+            .ok_or(ExportError::UnknownTemplate(template_id))?;
+        if values.len() != template.fields.len() {
+            return Err(ExportError::FieldCount {
+                template_id,
+                expected: template.fields.len(),
+                actual: values.len(),
+            });
+        }
+
+        let mut record = Vec::new();
+        for (field, (specifier, value)) in template.fields.iter().zip(values).enumerate() {
+            value.encode(specifier.field_length, &mut record)
+                .map_err(|error| {
+                    ExportError::InvalidValue {
+                        template_id,
+                        field,
+                        error,
+                    }
+                })?;
+        }
+        self.set(template_id).extend_from_slice(&record);
+        self.records = self.records.wrapping_add(1);
+        Ok(self)
+    }
+
+    /// Encode the message, and count its records towards the sequence number of the next
+    /// message for the Observation Domain.
+    pub fn finish(self) -> Result<Vec<u8>, ExportError> {
+        let length = IPFIX_HEADER_LENGTH +
+                     self.sets.iter().map(|(_, set)| SET_HEADER_LENGTH + set.len()).sum::<usize>();
+        if length > u16::MAX as usize {
+            return Err(ExportError::MessageTooLong(length));
+        }
+        let export_time = self.export_time.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as u32)
+        });
+        let sequence_number = self.exporter
+            .sequence_numbers
+            .entry(self.observation_domain_id)
+            .or_insert(0);
+
+        let mut out = Vec::with_capacity(length);
+        out.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+        out.extend_from_slice(&(length as u16).to_be_bytes());
+        out.extend_from_slice(&export_time.to_be_bytes());
+        out.extend_from_slice(&sequence_number.to_be_bytes());
+        out.extend_from_slice(&self.observation_domain_id.to_be_bytes());
+        for (set_id, set) in &self.sets {
+            out.extend_from_slice(&set_id.to_be_bytes());
+            out.extend_from_slice(&((SET_HEADER_LENGTH + set.len()) as u16).to_be_bytes());
+            out.extend_from_slice(set);
+        }
+
+        *sequence_number = sequence_number.wrapping_add(self.records);
+        Ok(out)
+    }
+
+    fn write_template(&mut self, template_id: u16) {
+        let template = &self.exporter.templates[&template_id];
+        let record = template.record.clone();
+        let set_id = match template.scope_field_count {
+            Some(_) => OPTIONS_TEMPLATE_SET_ID,
+            None => TEMPLATE_SET_ID,
+        };
+        self.set(set_id).extend_from_slice(&record);
+    }
+
+    // the set to add a record to, a new one unless the last set has the same id
+    fn set(&mut self, set_id: u16) -> &mut Vec<u8> {
+        if self.sets.last().map(|&(id, _)| id) != Some(set_id) {
+            self.sets.push((set_id, Vec::new()));
+        }
+        &mut self.sets.last_mut().unwrap().1
+    }
+}
+
+// A template record, or an options template record when there is a scope field count. Fails
+// like write_field_specifiers, with nothing written.
+pub(crate) fn write_template_record(out: &mut Vec<u8>,
+                                    template_id: u16,
+                                    scope_field_count: Option<u16>,
+                                    fields: &[FieldSpecifier])
+                                    -> Result<(), FieldSpecifier> {
+    let mut record = Vec::new();
+    record.extend_from_slice(&template_id.to_be_bytes());
+    record.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    if let Some(scope_field_count) = scope_field_count {
+        record.extend_from_slice(&scope_field_count.to_be_bytes());
+    }
+    write_field_specifiers(&mut record, fields)?;
+    out.extend_from_slice(&record);
+    Ok(())
+}
+
+// Field specifiers as they appear in a template record. An element id needs to fit in the 15
+// bits left by the enterprise bit, so the first field that doesn't is handed back, with nothing
+// written.
+pub(crate) fn write_field_specifiers(out: &mut Vec<u8>,
+                                     fields: &[FieldSpecifier])
+                                     -> Result<(), FieldSpecifier> {
+    if let Some(&field) = fields.iter().find(|field| field.ident >= 0x8000) {
+        return Err(field);
+    }
+    for field in fields {
+        match field.enterprise_number {
+            Some(enterprise_number) => {
+                out.extend_from_slice(&(field.ident | 0x8000).to_be_bytes());
+                out.extend_from_slice(&field.field_length.to_be_bytes());
+                out.extend_from_slice(&enterprise_number.to_be_bytes());
+            }
+            None => {
+                out.extend_from_slice(&field.ident.to_be_bytes());
+                out.extend_from_slice(&field.field_length.to_be_bytes());
+            }
+        }
+    }
+    Ok(())
+}
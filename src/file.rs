@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+
+use super::exporter::write_template_record;
+use super::{get_message_length, parse_message_header, parse_options_template_set,
+            parse_template_set, FieldSpecifier, IpfixConsumer, IpfixError, IpfixHeader,
+            MessageSets, ParsedMessage, IPFIX_HEADER_LENGTH, IPFIX_VERSION,
+            OPTIONS_TEMPLATE_SET_ID, SET_HEADER_LENGTH, TEMPLATE_SET_ID};
+
+// An IPFIX File (RFC 5655) is a plain stream of IPFIX messages, so everything in it belongs to
+// the one transport session
+const FILE_SESSION: u64 = 0;
+
+/// Reads the messages of an IPFIX File (RFC 5655) one after the other, decoding each with the
+/// templates of the messages before it.
+///
+/// Messages the consumer can't decode come out as errors of kind `InvalidData` wrapping the
+/// `IpfixError`, and reading carries on with the next message. A file that ends in the middle
+/// of a message, as one still being written or cut short by a crash does, ends the iteration
+/// without an error; `is_truncated` tells the two apart.
+pub struct FileReader<R> {
+    reader: R,
+    consumer: IpfixConsumer,
+    buffer: Vec<u8>,
+    done: bool,
+    truncated: bool,
+}
+
+impl<R: Read> FileReader<R> {
+    pub fn new(reader: R) -> FileReader<R> {
+        FileReader::with_consumer(reader, IpfixConsumer::new())
+    }
+
+    /// A reader decoding with `consumer`, e.g. one with templates imported from a snapshot.
+    pub fn with_consumer(reader: R, consumer: IpfixConsumer) -> FileReader<R> {
+        FileReader {
+            reader,
+            consumer,
+            buffer: Vec::new(),
+            done: false,
+            truncated: false,
+        }
+    }
+
+    pub fn consumer(&self) -> &IpfixConsumer {
+        &self.consumer
+    }
+
+    /// True when the file ended in the middle of a message.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Reads the next message into the buffer, or returns false at the end of the file
+    fn read_message(&mut self) -> io::Result<bool> {
+        self.buffer.resize(IPFIX_HEADER_LENGTH, 0);
+        let read = read_full(&mut self.reader, &mut self.buffer)?;
+        if read < IPFIX_HEADER_LENGTH {
+            self.truncated = read > 0;
+            return Ok(false);
+        }
+
+        let length = get_message_length(&self.buffer)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))? as usize;
+        if length < IPFIX_HEADER_LENGTH {
+            // there's no telling where the next message starts
+            let error = IpfixError::InvalidMessageLength {
+                offset: 2,
+                set_id: None,
+                length,
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+        self.buffer.resize(length, 0);
+        let read = read_full(&mut self.reader, &mut self.buffer[IPFIX_HEADER_LENGTH..])?;
+        if read < length - IPFIX_HEADER_LENGTH {
+            self.truncated = true;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for FileReader<R> {
+    type Item = io::Result<ParsedMessage<'static>>;
+
+    fn next(&mut self) -> Option<io::Result<ParsedMessage<'static>>> {
+        if self.done {
+            return None;
+        }
+        match self.read_message() {
+            Ok(true) => {}
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(error) => {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+        let message = self.consumer
+            .parse_message(FILE_SESSION, &self.buffer)
+            .map(ParsedMessage::into_owned)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error));
+        Some(message)
+    }
+}
+
+// Fills `buffer` from `reader` as far as the data goes, returning how many bytes it got
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Writes IPFIX messages to an IPFIX File (RFC 5655), making sure every data set in the file
+/// comes after the template it needs.
+///
+/// The writer keeps the templates and options templates of the messages written through it.
+/// After `rotate` switches to a new file, templates from earlier files are written again, in a
+/// message of their own, ahead of the first message with data that uses them.
+pub struct FileWriter<W> {
+    writer: W,
+    templates: BTreeMap<TemplateKey, FileTemplate>,
+    // templates already in the current file
+    written: BTreeSet<TemplateKey>,
+}
+
+// (observation domain id, template id)
+type TemplateKey = (u32, u16);
+
+struct FileTemplate {
+    // None for templates, Some for options templates
+    scope_field_count: Option<u16>,
+    fields: Vec<FieldSpecifier>,
+}
+
+impl<W: Write> FileWriter<W> {
+    pub fn new(writer: W) -> FileWriter<W> {
+        FileWriter {
+            writer,
+            templates: BTreeMap::new(),
+            written: BTreeSet::new(),
+        }
+    }
+
+    /// Write one IPFIX message, such as one received from an exporter or encoded by
+    /// `IpfixExporter`, preceded by any templates its data sets need that aren't in the file
+    /// yet. Messages that can't be parsed fail with an error of kind `InvalidData`, and are not
+    /// written.
+    pub fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        let (header, sets) = message_sets(message)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let domain_id = header.observation_domain_id;
+        let mut missing = BTreeSet::new();
+        let mut defined = Vec::new();
+        for set in &sets {
+            match *set {
+                MessageSet::Templates(ref templates) => defined.extend(templates),
+                MessageSet::Data(template_id) => {
+                    let key = (domain_id, template_id);
+                    // a template earlier in the same message is no use to data before it, but
+                    // one written ahead of the message is
+                    if !self.written.contains(&key) && self.templates.contains_key(&key) &&
+                       !defined.iter().any(|&&(id, _)| id == template_id) {
+                        missing.insert(key);
+                    }
+                }
+            }
+        }
+
+        self.write_templates(&header, &missing)?;
+        self.writer.write_all(message)?;
+        self.written.extend(missing);
+
+        for set in sets {
+            if let MessageSet::Templates(templates) = set {
+                for (template_id, template) in templates {
+                    self.define(domain_id, template_id, template);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Carry on writing to `writer`, a new file, and return the writer of the old one.
+    pub fn rotate(&mut self, writer: W) -> W {
+        self.written.clear();
+        ::std::mem::replace(&mut self.writer, writer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // Adds, replaces or withdraws a template (RFC 7011 §8.1)
+    fn define(&mut self, domain_id: u32, template_id: u16, template: Option<FileTemplate>) {
+        let key = (domain_id, template_id);
+        match template {
+            Some(template) => {
+                self.templates.insert(key, template);
+                self.written.insert(key);
+            }
+            // a withdrawal of the set id withdraws every template of that kind
+            None if template_id == TEMPLATE_SET_ID || template_id == OPTIONS_TEMPLATE_SET_ID => {
+                let options = template_id == OPTIONS_TEMPLATE_SET_ID;
+                let withdrawn = |&(domain, _): &TemplateKey, template: &FileTemplate| {
+                    domain == domain_id && template.scope_field_count.is_some() == options
+                };
+                let keys = self.templates
+                    .iter()
+                    .filter(|&(key, template)| withdrawn(key, template))
+                    .map(|(&key, _)| key)
+                    .collect::<Vec<_>>();
+                for key in keys {
+                    self.templates.remove(&key);
+                    self.written.remove(&key);
+                }
+            }
+            None => {
+                self.templates.remove(&key);
+                self.written.remove(&key);
+            }
+        }
+    }
+
+    // Writes the templates in messages of their own, with the header fields of the message
+    // that needs them
+    fn write_templates(&mut self,
+                       header: &IpfixHeader,
+                       keys: &BTreeSet<TemplateKey>)
+                       -> io::Result<()> {
+        let mut sets = Vec::<(u16, Vec<u8>)>::new();
+        let mut length = IPFIX_HEADER_LENGTH;
+        for &(domain_id, template_id) in keys {
+            let template = &self.templates[&(domain_id, template_id)];
+            let mut record = Vec::new();
+            write_template_record(&mut record,
+                                  template_id,
+                                  template.scope_field_count,
+                                  &template.fields)
+                .map_err(|field| {
+                    let message = format!("element id {} can't be encoded in a template",
+                                          field.ident);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })?;
+            let set_id = match template.scope_field_count {
+                Some(_) => OPTIONS_TEMPLATE_SET_ID,
+                None => TEMPLATE_SET_ID,
+            };
+
+            if length + SET_HEADER_LENGTH + record.len() > u16::MAX as usize {
+                write_sets(&mut self.writer, header, &sets)?;
+                sets.clear();
+                length = IPFIX_HEADER_LENGTH;
+            }
+            if sets.last().map(|&(id, _)| id) != Some(set_id) {
+                sets.push((set_id, Vec::new()));
+                length += SET_HEADER_LENGTH;
+            }
+            length += record.len();
+            sets.last_mut().unwrap().1.extend_from_slice(&record);
+        }
+        if !sets.is_empty() {
+            write_sets(&mut self.writer, header, &sets)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_sets<W: Write>(writer: &mut W,
+                        header: &IpfixHeader,
+                        sets: &[(u16, Vec<u8>)])
+                        -> io::Result<()> {
+    let length = IPFIX_HEADER_LENGTH +
+                 sets.iter().map(|(_, set)| SET_HEADER_LENGTH + set.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+    out.extend_from_slice(&(length as u16).to_be_bytes());
+    out.extend_from_slice(&header.export_time.to_be_bytes());
+    out.extend_from_slice(&header.sequence_number.to_be_bytes());
+    out.extend_from_slice(&header.observation_domain_id.to_be_bytes());
+    for (set_id, set) in sets {
+        out.extend_from_slice(&set_id.to_be_bytes());
+        out.extend_from_slice(&((SET_HEADER_LENGTH + set.len()) as u16).to_be_bytes());
+        out.extend_from_slice(set);
+    }
+    writer.write_all(&out)
+}
+
+// What the writer needs to know of a set: the templates it defines, None for withdrawals, or
+// the template a data set needs
+enum MessageSet {
+    Templates(Vec<(u16, Option<FileTemplate>)>),
+    Data(u16),
+}
+
+fn message_sets(message: &[u8]) -> Result<(IpfixHeader, Vec<MessageSet>), IpfixError> {
+    let (remaining, header) = parse_message_header(message)?;
+    let mut sets = Vec::new();
+    for set in MessageSets::new(message, remaining) {
+        let (offset, set_header, set) = set?;
+        let set = match set_header.set_id {
+            TEMPLATE_SET_ID => {
+                let templates = parse_template_set(set, set_header, offset)?
+                    .records
+                    .into_iter()
+                    .map(|template| {
+                        (template.header.template_id,
+                         template_definition(None, template.field_specifiers))
+                    })
+                    .collect();
+                MessageSet::Templates(templates)
+            }
+            OPTIONS_TEMPLATE_SET_ID => {
+                let templates = parse_options_template_set(set, set_header, offset)?
+                    .records
+                    .into_iter()
+                    .map(|template| {
+                        (template.header.id,
+                         template_definition(Some(template.header.scope_field_count),
+                                             template.field_specifiers))
+                    })
+                    .collect();
+                MessageSet::Templates(templates)
+            }
+            set_id if set_id > 255 => MessageSet::Data(set_id),
+            // sets ids 4 to 255 are reserved, and ignored like the consumer does
+            _ => continue,
+        };
+        sets.push(set);
+    }
+    Ok((header, sets))
+}
+
+// None for a withdrawal, which has no fields
+fn template_definition(scope_field_count: Option<u16>,
+                       fields: Vec<FieldSpecifier>)
+                       -> Option<FileTemplate> {
+    if fields.is_empty() {
+        None
+    } else {
+        Some(FileTemplate {
+            scope_field_count,
+            fields,
+        })
+    }
+}
@@ -0,0 +1,64 @@
+// Reads Information Element records out of a registry in the format of the IANA IPFIX registry
+// (https://www.iana.org/assignments/ipfix/ipfix.xml). Shared by build.rs, which generates the
+// built-in table from data/ipfix.xml, and Registry::load_xml.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use xml::reader::{EventReader, Result, XmlEvent};
+
+const ELEMENTS_REGISTRY_ID: &str = "ipfix-information-elements";
+
+/// The child elements of one <record>, by name, with surrounding whitespace trimmed.
+pub type Record = BTreeMap<String, String>;
+
+/// Every <record> of the Information Elements registry (<registry
+/// id="ipfix-information-elements">), in document order. Records of the other IANA registries
+/// in the same file are left out.
+pub fn element_records<R: Read>(reader: R) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut registries = Vec::<String>::new();
+    // child elements of the <record> being read, and the one whose text is being collected
+    let mut record: Option<Record> = None;
+    let mut field: Option<String> = None;
+    let mut depth = 0;
+    let mut record_depth = 0;
+
+    for event in EventReader::new(reader) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                depth += 1;
+                let in_elements = registries.last().is_some_and(|id| id == ELEMENTS_REGISTRY_ID);
+                if name.local_name == "registry" {
+                    let id = attributes.iter().find(|attribute| attribute.name.local_name == "id");
+                    registries.push(id.map_or(String::new(), |id| id.value.clone()));
+                } else if name.local_name == "record" && in_elements && record.is_none() {
+                    record = Some(Record::new());
+                    record_depth = depth;
+                } else if record.is_some() && depth == record_depth + 1 {
+                    field = Some(name.local_name);
+                }
+            }
+            XmlEvent::Characters(text) => {
+                if let (Some(record), Some(field)) = (record.as_mut(), field.as_ref()) {
+                    record.entry(field.clone()).or_default().push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "registry" {
+                    registries.pop();
+                } else if record.is_some() && depth == record_depth {
+                    let mut fields = record.take().unwrap();
+                    for value in fields.values_mut() {
+                        *value = value.trim().to_string();
+                    }
+                    records.push(fields);
+                }
+                field = None;
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(records)
+}
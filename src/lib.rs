@@ -1,36 +1,155 @@
 #[macro_use]
 extern crate nom;
+extern crate xml;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 pub mod conversions;
+pub mod error;
+pub mod exporter;
+pub mod file;
+mod iana_xml;
+mod json;
+pub mod list;
+mod netflow;
+mod pending;
 pub mod printer;
-
+pub mod registry;
+pub mod sequence;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod sflow;
+mod snapshot;
+pub mod stream;
+pub mod value;
+
+pub use error::{ExportError, IpfixError, SflowError, ValueError};
+pub use exporter::{IpfixExporter, MessageBuilder};
+pub use file::{FileReader, FileWriter};
+pub use list::{ListSemantic, StructuredList};
 pub use printer::*;
+pub use registry::{DataType, InformationElement, Registry, Semantics, Status};
+pub use sequence::{SequenceEvent, SequenceStats};
+pub use sflow::{SflowDatagram, SflowDecoder};
+pub use stream::StreamDecoder;
+#[cfg(feature = "serde")]
+pub use serialize::WithRegistry;
+pub use value::FieldValue;
 
 use conversions::*;
-use std::collections::HashMap;
+use pending::PendingQueue;
+use sequence::SequenceTracker;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const IPFIX_VERSION: u16 = 10;
+const IPFIX_HEADER_LENGTH: usize = 16;
+const SET_HEADER_LENGTH: usize = 4;
+const TEMPLATE_SET_ID: u16 = 2;
+const OPTIONS_TEMPLATE_SET_ID: u16 = 3;
+// field length in a template that marks a variable-length Information Element (RFC 7011 §7)
+const VARIABLE_LENGTH: u16 = 65535;
+// the boundary sets are usually padded to (RFC 7011 §3.3.1)
+const SET_ALIGNMENT: usize = 4;
+// how deep structured lists are decoded inside each other; deeper ones are left as octet arrays
+const MAX_LIST_DEPTH: usize = 8;
+
+/// Identifies the transport session a message was received on. Templates are scoped to a
+/// session and an Observation Domain, so any number of exporters can share one consumer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SessionKey {
+    /// The address of the exporter, for messages received over UDP
+    Exporter(SocketAddr),
+    /// Any other session the caller keeps track of, such as a TCP connection or a file
+    Opaque(u64),
+}
+
+impl From<SocketAddr> for SessionKey {
+    fn from(addr: SocketAddr) -> SessionKey {
+        SessionKey::Exporter(addr)
+    }
+}
+
+impl From<u64> for SessionKey {
+    fn from(id: u64) -> SessionKey {
+        SessionKey::Opaque(id)
+    }
+}
+
+/// Identifies an Information Element. Elements defined by IANA have an enterprise number of 0,
+/// vendor elements carry the vendor's Private Enterprise Number, and the same id means something
+/// different in every enterprise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ElementId {
+    pub enterprise_number: u32,
+    pub id: u16,
+}
 
-// Using binary trees allows the JSON output to stay in the same order for each record, but its performance is slower than a HashMap.
-// Should make functions generic and allow this to be changed by a bool flag.
-use std::collections::BTreeMap;
+impl ElementId {
+    pub fn iana(id: u16) -> ElementId {
+        ElementId {
+            enterprise_number: 0,
+            id,
+        }
+    }
+
+    pub fn enterprise(enterprise_number: u32, id: u16) -> ElementId {
+        ElementId {
+            enterprise_number,
+            id,
+        }
+    }
+}
+
+impl From<u16> for ElementId {
+    fn from(id: u16) -> ElementId {
+        ElementId::iana(id)
+    }
+}
+
+// (session, observation domain id, template id)
+type TemplateKey = (SessionKey, u32, u16);
 
 pub struct IpfixConsumer {
-    templates: HashMap<u16, Template>,
-    options_templates: HashMap<u16, OptionsTemplate>,
+    templates: HashMap<TemplateKey, Template>,
+    options_templates: HashMap<TemplateKey, OptionsTemplate>,
+    sequences: HashMap<(SessionKey, u32), SequenceTracker>,
+    template_timeout: Option<Duration>,
+    pending: Option<PendingQueue>,
+    // sessions over TCP or SCTP, where templates live as long as the session
+    streams: HashSet<SessionKey>,
+    // what tells structured lists apart from other fields
+    registry: Cow<'static, Registry>,
 }
 
-#[allow(dead_code)]
-struct IpfixHeader {
-    version: u16,
-    length: u16,
-    export_time: u32,
-    sequence_number: u32,
-    observation_domain_id: u32,
+/// The header of a message. NetFlow v9 packet headers fit in it too: the length is that of the
+/// packet, the export time its UNIX Secs, and the Observation Domain id its Source ID. So do
+/// NetFlow v5 headers, with the engine type and engine id as the Observation Domain id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpfixHeader {
+    pub version: u16,
+    pub length: u16,
+    pub export_time: u32, // seconds since the UNIX epoch
+    pub sequence_number: u32,
+    pub observation_domain_id: u32,
 }
 
-#[allow(dead_code)]
-struct IpfixMessage<'a> {
-    header: IpfixHeader,
-    sets: Vec<Set<'a>>,
+/// A message as returned by `IpfixConsumer::parse_message`: its header, and the data sets it
+/// carried. Template sets are consumed by the parser and are not included.
+pub struct ParsedMessage<'a> {
+    pub header: IpfixHeader,
+    pub sets: Vec<DataSet<'a>>,
+    /// Data sets that arrived in earlier messages, before their template, and were decoded
+    /// now that this message brought it. Each comes with the header of its own message.
+    pub recovered: Vec<(IpfixHeader, DataSet<'a>)>,
+    /// Set when the sequence number is not the one expected after the previous message from
+    /// the same Observation Domain.
+    pub sequence_event: Option<SequenceEvent>,
+    /// Milliseconds since the exporter started, at the export time of a NetFlow v5 or v9
+    /// packet. `None` for IPFIX messages.
+    pub sys_uptime: Option<u32>,
 }
 
 pub enum Set<'a> {
@@ -39,9 +158,10 @@ pub enum Set<'a> {
     DataSet(DataSet<'a>),
 }
 
-struct SetHeader {
-    set_id: u16, // 2: Template Set, 3: Options Template Set, >255: Data Set
-    length: u16,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SetHeader {
+    pub set_id: u16, // 2: Template Set, 3: Options Template Set, >255: Data Set
+    pub length: u16,
 }
 
 pub struct TemplateSet {
@@ -58,6 +178,7 @@ struct TemplateHeader {
 struct Template {
     header: TemplateHeader,
     field_specifiers: Vec<FieldSpecifier>,
+    received: Instant,
 }
 
 pub struct OptionsTemplateSet {
@@ -69,31 +190,127 @@ pub struct OptionsTemplateSet {
 struct OptionsTemplateHeader {
     id: u16,
     field_count: u16,
-    #[allow(dead_code)]
     scope_field_count: u16,
 }
 
 struct OptionsTemplate {
     header: OptionsTemplateHeader,
     field_specifiers: Vec<FieldSpecifier>,
+    received: Instant,
 }
 
-struct FieldSpecifier {
+/// One field of a template: the Information Element it carries and its length in a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldSpecifier {
     ident: u16, // 15b in msg
     field_length: u16,
-    #[allow(dead_code)]
     enterprise_number: Option<u32>,
 }
 
+impl FieldSpecifier {
+    /// A field carrying `element` in `field_length` bytes, or 65535 for a variable-length
+    /// field. Element ids are 15 bits in a template, so they must be below 32768.
+    pub fn new<E: Into<ElementId>>(element: E, field_length: u16) -> FieldSpecifier {
+        let element = element.into();
+        FieldSpecifier {
+            ident: element.id,
+            field_length,
+            enterprise_number: match element.enterprise_number {
+                0 => None,
+                enterprise_number => Some(enterprise_number),
+            },
+        }
+    }
+
+    pub fn element_id(&self) -> ElementId {
+        ElementId::enterprise(self.enterprise_number.unwrap_or(0), self.ident)
+    }
+
+    /// Length of the field as given in the template, 65535 for variable-length fields.
+    pub fn field_length(&self) -> u16 {
+        self.field_length
+    }
+}
+
 pub struct DataSet<'a> {
-    #[allow(dead_code)]
-    header: SetHeader,
+    pub header: SetHeader,
     records: Vec<DataRecord<'a>>,
+    template_kind: TemplateKind,
+}
+
+/// The kind of template the records of a data set were decoded with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateKind {
+    /// A template, for flow records and the like.
+    Template,
+    /// An options template, whose records describe the exporter or the other records: their
+    /// first `scope_field_count` fields say what the rest apply to.
+    OptionsTemplate { scope_field_count: u16 },
+}
+
+/// A record of an options data set, seen as the scope fields it starts with and the option
+/// fields that follow, as returned by `DataSet::options_records`.
+#[derive(Clone, Copy)]
+pub struct OptionsRecord<'r, 'a: 'r> {
+    record: &'r DataRecord<'a>,
+    scope_field_count: usize,
 }
 
 #[derive(PartialEq)]
 pub struct DataRecord<'a> {
-    values: BTreeMap<u16, &'a [u8]>,
+    // in template order; an Information Element can appear more than once
+    fields: Vec<(FieldSpecifier, Cow<'a, [u8]>)>,
+    // the decoded structured lists, by the index of their field
+    lists: Vec<(usize, StructuredList<'a>)>,
+}
+
+// The templates the structured lists of a data set can refer to: those of the session and
+// Observation Domain the set was received on.
+#[derive(Clone, Copy)]
+struct TemplateScope<'t> {
+    templates: &'t HashMap<TemplateKey, Template>,
+    options_templates: &'t HashMap<TemplateKey, OptionsTemplate>,
+    session: SessionKey,
+    domain_id: u32,
+    // None when the templates don't expire
+    template_timeout: Option<Duration>,
+    // the number of lists the records being decoded are in
+    depth: usize,
+    // the types of the elements, to find the fields that hold lists
+    registry: &'t Registry,
+}
+
+impl<'t> TemplateScope<'t> {
+    fn fields(&self, template_id: u16) -> Option<&'t [FieldSpecifier]> {
+        let key = (self.session, self.domain_id, template_id);
+        let live = |received: Instant| {
+            self.template_timeout.is_none_or(|timeout| received.elapsed() <= timeout)
+        };
+        match self.templates.get(&key) {
+            Some(template) if live(template.received) => Some(&template.field_specifiers),
+            Some(_) => None,
+            None => {
+                self.options_templates
+                    .get(&key)
+                    .filter(|template| live(template.received))
+                    .map(|template| &template.field_specifiers[..])
+            }
+        }
+    }
+
+    // the scope of the records in a list, or None if lists are nested too deep to go on
+    fn nested(&self) -> Option<TemplateScope<'t>> {
+        if self.depth >= MAX_LIST_DEPTH {
+            return None;
+        }
+        Some(TemplateScope { depth: self.depth + 1, ..*self })
+    }
+}
+
+impl Default for IpfixConsumer {
+    fn default() -> IpfixConsumer {
+        IpfixConsumer::new()
+    }
 }
 
 impl IpfixConsumer {
@@ -101,119 +318,651 @@ impl IpfixConsumer {
         IpfixConsumer {
             templates: HashMap::new(),
             options_templates: HashMap::new(),
+            sequences: HashMap::new(),
+            template_timeout: None,
+            pending: None,
+            streams: HashSet::new(),
+            registry: Cow::Borrowed(registry::default_registry()),
+        }
+    }
+
+    /// Expire templates that have not been refreshed within `timeout`, as RFC 7011 §8.4 asks
+    /// of collectors receiving over UDP. Templates never expire by default, nor ever on stream
+    /// sessions.
+    pub fn with_template_timeout(mut self, timeout: Duration) -> IpfixConsumer {
+        self.template_timeout = Some(timeout);
+        self
+    }
+
+    /// Look up the types of elements in `registry` in place of the IANA registry, so that the
+    /// lists in fields of vendor elements typed basicList, subTemplateList or
+    /// subTemplateMultiList are decoded too.
+    pub fn with_registry(mut self, registry: Registry) -> IpfixConsumer {
+        self.registry = Cow::Owned(registry);
+        self
+    }
+
+    /// Hold on to data sets that arrive before their template, instead of failing the message
+    /// with `IpfixError::UnknownTemplate`. Up to `max_bytes` of set data is kept, for at most
+    /// `max_age`; the sets are decoded into `ParsedMessage::recovered` once the template arrives.
+    /// A set that turns out to be malformed then fails the message that brought the template,
+    /// with the offset the set had in its own message.
+    pub fn with_pending_queue(mut self, max_bytes: usize, max_age: Duration) -> IpfixConsumer {
+        self.pending = Some(PendingQueue::new(max_bytes, max_age));
+        self
+    }
+
+    /// Bytes of data sets currently waiting for their template.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending.as_ref().map_or(0, PendingQueue::bytes)
+    }
+
+    /// Drop every template that has outlived the template timeout. Expired templates are never
+    /// used to decode data either way, this only frees the memory they take up.
+    pub fn expire_templates(&mut self) {
+        if let Some(timeout) = self.template_timeout {
+            let streams = &self.streams;
+            self.templates.retain(|&(session, _, _), template| {
+                streams.contains(&session) || template.received.elapsed() <= timeout
+            });
+            self.options_templates.retain(|&(session, _, _), template| {
+                streams.contains(&session) || template.received.elapsed() <= timeout
+            });
+        }
+    }
+
+    #[inline]
+    fn template_scope(&self, session: SessionKey, domain_id: u32) -> TemplateScope<'_> {
+        TemplateScope {
+            templates: &self.templates,
+            options_templates: &self.options_templates,
+            session,
+            domain_id,
+            template_timeout: self.template_timeout.filter(|_| !self.streams.contains(&session)),
+            depth: 0,
+            registry: &self.registry,
+        }
+    }
+
+    fn is_expired(&self, session: SessionKey, received: Instant) -> bool {
+        match self.template_timeout {
+            Some(timeout) => received.elapsed() > timeout && !self.streams.contains(&session),
+            None => false,
         }
     }
 
+    /// Treat `session` as a TCP or SCTP session: its templates never expire, and a template id
+    /// can only be reused after the template is withdrawn (RFC 7011 §8), otherwise
+    /// `parse_message` fails with `IpfixError::TemplateRedefined`. `StreamDecoder` does this
+    /// for the sessions it decodes.
+    pub fn open_stream<S: Into<SessionKey>>(&mut self, session: S) {
+        self.streams.insert(session.into());
+    }
+
+    /// Forget everything about `session`: its templates, sequence numbers and data sets waiting
+    /// for a template. Call it when a TCP connection closes, as templates don't outlive the
+    /// session they were sent on.
+    pub fn close_session<S: Into<SessionKey>>(&mut self, session: S) {
+        let session = session.into();
+        self.streams.remove(&session);
+        self.templates.retain(|&(s, _, _), _| s != session);
+        self.options_templates.retain(|&(s, _, _), _| s != session);
+        self.sequences.retain(|&(s, _), _| s != session);
+        if let Some(ref mut queue) = self.pending {
+            queue.remove_session(session);
+        }
+    }
+
+    /// Sequence number accounting for one Observation Domain of `session`, or `None` if no
+    /// message has been parsed for it.
+    pub fn sequence_stats<S>(&self, session: S, domain_id: u32) -> Option<SequenceStats>
+        where S: Into<SessionKey>
+    {
+        self.sequences.get(&(session.into(), domain_id)).map(SequenceTracker::stats)
+    }
+
+    #[inline]
+    fn add_template(&mut self,
+                    session: SessionKey,
+                    domain_id: u32,
+                    template: Template)
+                    -> Result<Vec<(IpfixHeader, DataSet<'static>)>, IpfixError> {
+        let key = (session, domain_id, template.header.template_id);
+        // a template id refers to one template or options template at a time
+        self.options_templates.remove(&key);
+        let recovered = self.recover_pending(&key, |data, set_header, scope, offset| {
+            parse_data_set(data, set_header, &template, scope, offset)
+        });
+        self.templates.insert(key, template);
+        recovered
+    }
+
     #[inline]
-    fn add_template(&mut self, template: Template) {
-        let id = template.header.template_id;
-        self.templates.insert(id, template);
+    fn add_options_template(&mut self,
+                            session: SessionKey,
+                            domain_id: u32,
+                            template: OptionsTemplate)
+                            -> Result<Vec<(IpfixHeader, DataSet<'static>)>, IpfixError> {
+        let key = (session, domain_id, template.header.id);
+        self.templates.remove(&key);
+        let recovered = self.recover_pending(&key, |data, set_header, scope, offset| {
+            parse_options_set(data, set_header, &template, scope, offset)
+        });
+        self.options_templates.insert(key, template);
+        recovered
     }
 
+    // A queued set that turns out to be malformed fails the message that brought its template,
+    // with the offset it had in its own message
     #[inline]
-    fn add_options_template(&mut self, template: OptionsTemplate) {
-        let id = template.header.id;
-        self.options_templates.insert(id, template);
+    fn recover_pending<F>(&mut self,
+                          key: &TemplateKey,
+                          decode: F)
+                          -> Result<Vec<(IpfixHeader, DataSet<'static>)>, IpfixError>
+        where F: for<'d> Fn(&'d [u8], SetHeader, &TemplateScope, usize)
+                            -> Result<DataSet<'d>, IpfixError>
+    {
+        let sets = match self.pending {
+            Some(ref mut queue) => queue.take(key),
+            None => return Ok(Vec::new()),
+        };
+        let scope = self.template_scope(key.0, key.1);
+        sets.into_iter()
+            .map(|set| {
+                let dataset = decode(&set.data, set.set_header, &scope, set.offset)?;
+                Ok((set.header, dataset.into_owned()))
+            })
+            .collect()
     }
 
+    // True when a template would replace a different one on a stream session, where templates
+    // can only be resent unchanged
     #[inline]
-    pub fn parse_message<'a>(&'a mut self, data: &'a [u8]) -> Result<Vec<DataSet>, &'static str> {
+    fn redefines(&self,
+                 key: &TemplateKey,
+                 scope_field_count: Option<u16>,
+                 fields: &[FieldSpecifier])
+                 -> bool {
+        if !self.streams.contains(&key.0) {
+            return false;
+        }
+        if let Some(template) = self.templates.get(key) {
+            return scope_field_count.is_some() || template.field_specifiers != fields;
+        }
+        if let Some(template) = self.options_templates.get(key) {
+            return scope_field_count != Some(template.header.scope_field_count) ||
+                   template.field_specifiers != fields;
+        }
+        false
+    }
+
+    #[inline]
+    fn withdraw_template(&mut self, session: SessionKey, domain_id: u32, template_id: u16) {
+        // RFC 7011 §8.1: withdrawing the template set id withdraws every template
+        if template_id == TEMPLATE_SET_ID {
+            self.templates.retain(|&(s, d, _), _| (s, d) != (session, domain_id));
+        } else {
+            self.templates.remove(&(session, domain_id, template_id));
+        }
+    }
+
+    #[inline]
+    fn withdraw_options_template(&mut self,
+                                 session: SessionKey,
+                                 domain_id: u32,
+                                 template_id: u16) {
+        if template_id == OPTIONS_TEMPLATE_SET_ID {
+            self.options_templates.retain(|&(s, d, _), _| (s, d) != (session, domain_id));
+        } else {
+            self.options_templates.remove(&(session, domain_id, template_id));
+        }
+    }
+
+    /// Parses one message received on `session`, which is usually the exporter's `SocketAddr`.
+    /// NetFlow v9 (RFC 3954) packets are accepted too, and decoded into the same data sets, as
+    /// are NetFlow v5 packets: their records come in one data set with template id 0, with
+    /// fields mapped to the equivalent Information Elements. Vendor-specific NetFlow v9 field
+    /// types, 32768 and up, become elements of enterprise 9 (Cisco) without the top bit, so
+    /// type 45003 is `ElementId::enterprise(9, 12235)`.
+    #[inline]
+    pub fn parse_message<'a, S>(&mut self,
+                                session: S,
+                                data: &'a [u8])
+                                -> Result<ParsedMessage<'a>, IpfixError>
+        where S: Into<SessionKey>
+    {
         // this should be 1:1 with UDP datagrams
-        // we aren't currently using any of the data from the ipfix message header but we still
-        // need to chop it off
-        if let nom::IResult::Done(bytes, _) = parse_ipfix_header(data) {
-            let mut remaining_bytes = bytes;
-            let mut datasets = Vec::<DataSet>::new();
-            loop {
-                if let nom::IResult::Done(bytes, set_header) = parse_set_header(remaining_bytes) {
-                    let set_length = (set_header.length - 4) as usize;
-                    let set_bytes = &bytes.clone()[0..set_length];
-                    if bytes.len() - set_length > 0 {
-                        remaining_bytes = &bytes[set_length..bytes.len()];
+        // the version and length from the ipfix message header are used to validate the
+        // message, and the observation domain id to find its templates
+        let session = session.into();
+        match data.get(0..2).and_then(be_buf_to_u16) {
+            Some(netflow::NETFLOW_V9_VERSION) => {
+                let (remaining_bytes, header, sys_uptime) = netflow::parse_header(data)?;
+                return self.parse_body(session, header, Some(sys_uptime), data, remaining_bytes);
+            }
+            Some(netflow::NETFLOW_V5_VERSION) => {
+                let (header, sys_uptime, set) = netflow::parse_v5_packet(data)?;
+                let tracker = self.sequences
+                    .entry((session, header.observation_domain_id))
+                    .or_default();
+                let sequence_event = tracker.update(header.sequence_number,
+                                                    set.records.len() as u32);
+                return Ok(ParsedMessage {
+                    header,
+                    sets: vec![set],
+                    recovered: Vec::new(),
+                    sequence_event,
+                    sys_uptime: Some(sys_uptime),
+                });
+            }
+            _ => {}
+        }
+
+        let (remaining_bytes, header) = parse_message_header(data)?;
+        self.parse_body(session, header, None, data, remaining_bytes)
+    }
+
+    // Decodes the sets of a message, and keeps track of its sequence number
+    #[inline]
+    fn parse_body<'a>(&mut self,
+                      session: SessionKey,
+                      header: IpfixHeader,
+                      sys_uptime: Option<u32>,
+                      data: &'a [u8],
+                      remaining_bytes: &'a [u8])
+                      -> Result<ParsedMessage<'a>, IpfixError> {
+
+        let domain_id = header.observation_domain_id;
+        let mut recovered = Vec::new();
+        let result = self.parse_sets(session, &header, data, remaining_bytes, &mut recovered);
+        let sets = match result {
+            Ok(sets) => sets,
+            Err(err) => {
+                // there is no telling how many records this message carried, so the next
+                // sequence number can't be predicted either
+                if let Some(tracker) = self.sequences.get_mut(&(session, domain_id)) {
+                    tracker.resync();
+                }
+                return Err(err);
+            }
+        };
+
+        let tracker = self.sequences.entry((session, domain_id)).or_default();
+        let sequence_event = match sets {
+            // NetFlow v9 sequence numbers count packets rather than records
+            _ if header.version == netflow::NETFLOW_V9_VERSION => {
+                tracker.update(header.sequence_number, 1)
+            }
+            Some(ref sets) => {
+                let records = sets.iter().fold(0, |sum, set| sum + set.records.len()) as u32;
+                tracker.update(header.sequence_number, records)
+            }
+            None => {
+                // some sets went to the pending queue, and their records can't be counted yet
+                tracker.resync();
+                None
+            }
+        };
+
+        Ok(ParsedMessage {
+            header,
+            sets: sets.unwrap_or_else(Vec::new),
+            recovered,
+            sequence_event,
+            sys_uptime,
+        })
+    }
+
+    // Returns the data sets in the message, with None in place of the ones waiting in the
+    // pending queue. Sets that come out of the queue go to `recovered`.
+    #[inline]
+    fn parse_sets<'a>(&mut self,
+                      session: SessionKey,
+                      header: &IpfixHeader,
+                      data: &'a [u8],
+                      remaining_bytes: &'a [u8],
+                      recovered: &mut Vec<(IpfixHeader, DataSet<'a>)>)
+                      -> Result<Option<Vec<DataSet<'a>>>, IpfixError> {
+        let domain_id = header.observation_domain_id;
+        let mut datasets = Vec::<DataSet>::new();
+        let mut deferred = false;
+        for set in MessageSets::new(data, remaining_bytes) {
+            let (offset, set_header, set_bytes) = set?;
+            let key = (session, domain_id, set_header.set_id);
+            let set = match (header.version, set_header.set_id) {
+                (IPFIX_VERSION, TEMPLATE_SET_ID) => {
+                    Set::TemplateSet(parse_template_set(set_bytes, set_header, offset)?)
+                }
+                (IPFIX_VERSION, OPTIONS_TEMPLATE_SET_ID) => {
+                    let set = parse_options_template_set(set_bytes, set_header, offset)?;
+                    Set::OptionsTemplateSet(set)
+                }
+                (netflow::NETFLOW_V9_VERSION, netflow::TEMPLATE_FLOWSET_ID) => {
+                    let set = netflow::parse_template_flowset(set_bytes, set_header, offset)?;
+                    Set::TemplateSet(set)
+                }
+                (netflow::NETFLOW_V9_VERSION, netflow::OPTIONS_TEMPLATE_FLOWSET_ID) => {
+                    let set =
+                        netflow::parse_options_template_flowset(set_bytes, set_header, offset)?;
+                    Set::OptionsTemplateSet(set)
+                }
+                (_, 0..=255) => {
+                    // the other version's template set ids, or reserved, so there is nothing
+                    // here for us to decode
+                    continue;
+                }
+                _ => {
+                    // data set
+                    let template = self.templates
+                        .get(&key)
+                        .filter(|template| !self.is_expired(session, template.received));
+                    let options_template = self.options_templates
+                        .get(&key)
+                        .filter(|template| !self.is_expired(session, template.received));
+                    let scope = self.template_scope(session, domain_id);
+                    if let Some(template) = template {
+                        Set::DataSet(parse_data_set(set_bytes,
+                                                    set_header,
+                                                    template,
+                                                    &scope,
+                                                    offset)?)
+                    } else if let Some(options_template) = options_template {
+                        Set::DataSet(parse_options_set(set_bytes,
+                                                       set_header,
+                                                       options_template,
+                                                       &scope,
+                                                       offset)?)
                     } else {
-                        remaining_bytes = &[];
-                    }
-                    let set = match set_header.set_id.clone() {
-                        2 => {
-                            // template set
-                            Some(parse_template_set(set_bytes, set_header))
-                        }
-                        3 => {
-                            // options template set
-                            Some(parse_options_template_set(set_bytes, set_header))
-                        }
-                        _ => {
-                            // data set
-                            if self.templates.contains_key(&set_header.set_id) {
-                                let template = {
-                                    self.templates.get(&set_header.set_id).unwrap()
-                                };
-                                Some(parse_data_set(set_bytes, set_header, template))
-                            } else if self.options_templates.contains_key(&set_header.set_id) {
-                                let options_template = {
-                                    self.options_templates.get(&set_header.set_id).unwrap()
-                                };
-                                Some(parse_options_set(set_bytes, set_header, options_template))
-                            } else {
-                                None
+                        let queued = match self.pending {
+                            Some(ref mut queue) => {
+                                queue.push(key, *header, set_header, offset, set_bytes)
                             }
+                            None => false,
+                        };
+                        if !queued {
+                            return Err(IpfixError::UnknownTemplate {
+                                offset,
+                                set_id: Some(set_header.set_id),
+                            });
                         }
-                    };
-                    if let Some(nom::IResult::Done(_, object)) = set {
-                        match object {
-                            Set::TemplateSet(set) => {
-                                for template in set.records {
-                                    self.add_template(template);
-                                }
-                            }
-                            Set::OptionsTemplateSet(set) => {
-                                for template in set.records {
-                                    self.add_options_template(template);
-                                }
-                            }
-                            Set::DataSet(dataset) => {
-                                datasets.push(dataset);
-                            }
+                        deferred = true;
+                        continue;
+                    }
+                }
+            };
+            match set {
+                Set::TemplateSet(set) => {
+                    for template in set.records {
+                        let id = template.header.template_id;
+                        if template.header.field_count == 0 {
+                            self.withdraw_template(session, domain_id, id);
+                        } else if self.redefines(&(session, domain_id, id),
+                                                 None,
+                                                 &template.field_specifiers) {
+                            return Err(IpfixError::TemplateRedefined {
+                                offset,
+                                set_id: Some(set_header.set_id),
+                                template_id: id,
+                            });
+                        } else {
+                            recovered.extend(self.add_template(session, domain_id, template)?);
                         }
                     }
                 }
-                if remaining_bytes.len() == 0 as usize {
-                    break;
+                Set::OptionsTemplateSet(set) => {
+                    for template in set.records {
+                        let id = template.header.id;
+                        if template.header.field_count == 0 {
+                            self.withdraw_options_template(session, domain_id, id);
+                        } else if self.redefines(&(session, domain_id, id),
+                                                 Some(template.header.scope_field_count),
+                                                 &template.field_specifiers) {
+                            return Err(IpfixError::TemplateRedefined {
+                                offset,
+                                set_id: Some(set_header.set_id),
+                                template_id: id,
+                            });
+                        } else {
+                            let sets = self.add_options_template(session, domain_id, template)?;
+                            recovered.extend(sets);
+                        }
+                    }
+                }
+                Set::DataSet(dataset) => {
+                    datasets.push(dataset);
                 }
             }
-            return Ok(datasets);
         }
-        Err("Parsing failed")
+        if deferred {
+            return Ok(None);
+        }
+        Ok(Some(datasets))
+    }
+}
+
+impl<'a> ParsedMessage<'a> {
+    /// Copy the record values out of the message, so the parsed message can outlive it.
+    pub fn into_owned(self) -> ParsedMessage<'static> {
+        ParsedMessage {
+            header: self.header,
+            sets: self.sets.into_iter().map(DataSet::into_owned).collect(),
+            recovered: self.recovered
+                .into_iter()
+                .map(|(header, set)| (header, set.into_owned()))
+                .collect(),
+            sequence_event: self.sequence_event,
+            sys_uptime: self.sys_uptime,
+        }
+    }
+
+    /// The time of a NetFlow v5 or v9 uptime timestamp, such as flowStartSysUpTime and
+    /// flowEndSysUpTime (FIRST_SWITCHED and LAST_SWITCHED), or `None` for IPFIX messages. It is
+    /// only as precise as the export time, to the second.
+    pub fn uptime_to_time(&self, uptime: u32) -> Option<SystemTime> {
+        let sys_uptime = self.sys_uptime?;
+        let export_time = UNIX_EPOCH + Duration::from_secs(self.header.export_time as u64);
+        // the uptime wraps around every 49.7 days, so take whichever of before or after the
+        // export time is nearer
+        let age = sys_uptime.wrapping_sub(uptime) as i32;
+        if age >= 0 {
+            export_time.checked_sub(Duration::from_millis(age as u64))
+        } else {
+            export_time.checked_add(Duration::from_millis(-(age as i64) as u64))
+        }
+    }
+}
+
+impl<'a> DataSet<'a> {
+    /// Id of the template the records in this set were decoded with.
+    pub fn template_id(&self) -> u16 {
+        self.header.set_id
+    }
+
+    pub fn records(&self) -> &[DataRecord<'a>] {
+        &self.records
+    }
+
+    /// Whether the records were decoded with a template or an options template.
+    pub fn template_kind(&self) -> TemplateKind {
+        self.template_kind
+    }
+
+    pub fn is_options(&self) -> bool {
+        self.template_kind != TemplateKind::Template
+    }
+
+    /// The records with their scope fields apart from their option fields, or `None` if the
+    /// set was decoded with a template rather than an options template.
+    pub fn options_records(&self) -> Option<Vec<OptionsRecord<'_, 'a>>> {
+        let scope_field_count = match self.template_kind {
+            TemplateKind::OptionsTemplate { scope_field_count } => scope_field_count as usize,
+            TemplateKind::Template => return None,
+        };
+        let records = self.records
+            .iter()
+            .map(|record| {
+                OptionsRecord {
+                    record,
+                    scope_field_count,
+                }
+            })
+            .collect();
+        Some(records)
+    }
+
+    /// Copy the record values out of the message, so the set can outlive it.
+    pub fn into_owned(self) -> DataSet<'static> {
+        DataSet {
+            header: self.header,
+            records: self.records.into_iter().map(DataRecord::into_owned).collect(),
+            template_kind: self.template_kind,
+        }
     }
 }
 
+impl<'r, 'a> OptionsRecord<'r, 'a> {
+    /// The fields that say what the options apply to, such as an interface or a template.
+    pub fn scope_fields(&self) -> &'r [(FieldSpecifier, Cow<'a, [u8]>)] {
+        let fields = self.record.fields();
+        &fields[..self.scope_field_count.min(fields.len())]
+    }
+
+    pub fn option_fields(&self) -> &'r [(FieldSpecifier, Cow<'a, [u8]>)] {
+        let fields = self.record.fields();
+        &fields[self.scope_field_count.min(fields.len())..]
+    }
+
+    /// Raw value of the first scope field carrying an Information Element.
+    pub fn scope_value<E: Into<ElementId>>(&self, element: E) -> Option<&'r [u8]> {
+        find_value(self.scope_fields(), element.into())
+    }
+
+    /// Raw value of the first option field carrying an Information Element.
+    pub fn option_value<E: Into<ElementId>>(&self, element: E) -> Option<&'r [u8]> {
+        find_value(self.option_fields(), element.into())
+    }
+
+    /// The whole record, scope fields first.
+    pub fn record(&self) -> &'r DataRecord<'a> {
+        self.record
+    }
+}
+
+fn find_value<'r>(fields: &'r [(FieldSpecifier, Cow<[u8]>)],
+                  element: ElementId)
+                  -> Option<&'r [u8]> {
+    fields.iter().find(|&&(field, _)| field.element_id() == element).map(|(_, value)| &**value)
+}
+
 impl<'a> DataRecord<'a> {
+    /// Every field of the record with its raw value, in the order the template lists them.
+    pub fn fields(&self) -> &[(FieldSpecifier, Cow<'a, [u8]>)] {
+        &self.fields
+    }
+
+    /// Raw value of the first occurrence of an Information Element, as it appeared in the
+    /// message.
+    pub fn value<E: Into<ElementId>>(&self, element: E) -> Option<&[u8]> {
+        self.values(element).into_iter().next()
+    }
+
+    /// The first occurrence of an Information Element, decoded according to its type in the
+    /// IANA registry, or None if the record doesn't have it. Elements the registry doesn't know
+    /// are returned as octet arrays.
+    pub fn get<E: Into<ElementId>>(&self,
+                                   element: E)
+                                   -> Option<Result<FieldValue<'_>, ValueError>> {
+        self.get_with(registry::default_registry(), element)
+    }
+
+    /// Like `get`, with the type of the element looked up in `registry`.
+    pub fn get_with<E: Into<ElementId>>(&self,
+                                        registry: &Registry,
+                                        element: E)
+                                        -> Option<Result<FieldValue<'_>, ValueError>> {
+        let element = element.into();
+        let data = self.value(element)?;
+        let data_type = registry.get(element).map_or(DataType::OctetArray, |ie| ie.data_type);
+        Some(FieldValue::decode(data_type, data))
+    }
+
+    /// The first occurrence of a basicList, subTemplateList or subTemplateMultiList element,
+    /// with its records decoded. `None` if the record doesn't have it, or if the list could not
+    /// be decoded: it refers to a template the consumer doesn't have, or it is malformed. Its
+    /// raw value is still there.
+    pub fn list<E: Into<ElementId>>(&self, element: E) -> Option<&StructuredList<'a>> {
+        let element = element.into();
+        let index = self.fields.iter().position(|&(field, _)| field.element_id() == element)?;
+        self.field_list(index)
+    }
+
+    /// The decoded structured list of the field at `index` in `fields`.
+    pub fn field_list(&self, index: usize) -> Option<&StructuredList<'a>> {
+        self.lists.iter().find(|&&(i, _)| i == index).map(|(_, list)| list)
+    }
+
+    /// Raw values of every occurrence of an Information Element, in template order.
+    pub fn values<E: Into<ElementId>>(&self, element: E) -> Vec<&[u8]> {
+        let element = element.into();
+        self.fields
+            .iter()
+            .filter(|&&(field, _)| field.element_id() == element)
+            .map(|(_, value)| &**value)
+            .collect()
+    }
+
+    // The index in `fields` of every occurrence of each element, by element id. Records are
+    // printed and serialized in this order, repeated elements grouped together.
+    pub(crate) fn element_indices(&self) -> BTreeMap<ElementId, Vec<usize>> {
+        let mut elements = BTreeMap::<ElementId, Vec<usize>>::new();
+        for (index, &(field, _)) in self.fields.iter().enumerate() {
+            elements.entry(field.element_id()).or_default().push(index);
+        }
+        elements
+    }
+
+    pub fn into_owned(self) -> DataRecord<'static> {
+        DataRecord {
+            fields: self.fields
+                .into_iter()
+                .map(|(field, value)| (field, Cow::Owned(value.into_owned())))
+                .collect(),
+            lists: self.lists.into_iter().map(|(index, list)| (index, list.into_owned())).collect(),
+        }
+    }
+
     pub fn to_json(&self) {
-        for field in &self.values {
+        for field in &self.fields {
             println!("{:?}", field);
         }
     }
 }
 
 #[inline]
-pub fn is_template_set(data: &[u8]) -> Result<bool, &'static str> {
+pub fn is_template_set(data: &[u8]) -> Result<bool, IpfixError> {
     // big-endian 0x0f-0x10
-    if data.len() >= 18 {
-        let id = conversions::be_buf_to_u16(&data[16..18]);
-        Ok(id == 2 || id == 3)
-    } else {
-        Err("Data too short".into())
+    match data.get(16..18).and_then(conversions::be_buf_to_u16) {
+        Some(id) => Ok(id == 2 || id == 3),
+        None => {
+            Err(IpfixError::TruncatedHeader {
+                offset: 0,
+                set_id: None,
+                length: IPFIX_HEADER_LENGTH + 2,
+                available: data.len(),
+            })
+        }
     }
 }
 
 #[inline]
-pub fn get_message_length(data: &[u8]) -> Result<u16, &'static str> {
+pub fn get_message_length(data: &[u8]) -> Result<u16, IpfixError> {
     // big-endian, 0x02-0x03
-    if data.len() >= 3 {
-        Ok(conversions::be_buf_to_u16(&data[2..4]))
-    } else {
-        Err("Data too short".into())
-    }
+    data.get(2..4).and_then(conversions::be_buf_to_u16).ok_or(IpfixError::TruncatedHeader {
+        offset: 0,
+        set_id: None,
+        length: 4,
+        available: data.len(),
+    })
 }
 
 #[inline]
@@ -230,28 +979,28 @@ fn parse_ipfix_header(data: &[u8]) -> nom::IResult<&[u8], IpfixHeader> {
     // |                    Observation Domain ID                      |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-    named!(ipfix_version <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(message_length <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(export_time <&[u8], u32>, map!(take!(4), be_buf_to_u32));
-    named!(sequence_number <&[u8], u32>, map!(take!(4), be_buf_to_u32));
-    named!(observation_domain_id <&[u8], u32>, map!(take!(4), be_buf_to_u32));
-
-    named!(ipfix_header <&[u8], IpfixHeader>, chain!(
-        version: ipfix_version ~
-        length: message_length ~
-        time: export_time ~
-        sequence: sequence_number ~
-        domain_id: observation_domain_id,
-        || { IpfixHeader {
-            version: version,
-            length: length,
+    named!(ipfix_version <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(message_length <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(export_time <&[u8], u32>, map_opt!(take!(4), be_buf_to_u32));
+    named!(sequence_number <&[u8], u32>, map_opt!(take!(4), be_buf_to_u32));
+    named!(observation_domain_id <&[u8], u32>, map_opt!(take!(4), be_buf_to_u32));
+
+    named!(ipfix_header <&[u8], IpfixHeader>, do_parse!(
+        version: ipfix_version >>
+        length: message_length >>
+        time: export_time >>
+        sequence: sequence_number >>
+        domain_id: observation_domain_id >>
+        (IpfixHeader {
+            version,
+            length,
             export_time: time,
             sequence_number: sequence,
             observation_domain_id: domain_id
-        }}
+        })
     ));
 
-    ipfix_header(&data)
+    ipfix_header(data)
 }
 
 #[inline]
@@ -265,9 +1014,9 @@ fn parse_field_specifier(data: &[u8]) -> nom::IResult<&[u8], FieldSpecifier> {
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
     // if greater than 2^15, bit is 1, and subtract 2^15 for ident
-    named!(element_ident_with_enterprise_bit <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(field_length <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(enterprise_number <&[u8], u32>, map!(take!(4), be_buf_to_u32));
+    named!(element_ident_with_enterprise_bit <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(field_length <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(enterprise_number <&[u8], u32>, map_opt!(take!(4), be_buf_to_u32));
 
     named!(enterprise_field_specifier<&[u8], (u16, u32)>, tuple!(
         field_length,
@@ -283,7 +1032,7 @@ fn parse_field_specifier(data: &[u8]) -> nom::IResult<&[u8], FieldSpecifier> {
                                           FieldSpecifier {
                                               enterprise_number: Some(enterprise_number),
                                               ident: id - 32768,
-                                              field_length: field_length,
+                                              field_length,
                                           });
             }
         } else {
@@ -292,7 +1041,7 @@ fn parse_field_specifier(data: &[u8]) -> nom::IResult<&[u8], FieldSpecifier> {
                                           FieldSpecifier {
                                               enterprise_number: None,
                                               ident: id,
-                                              field_length: field_length,
+                                              field_length,
                                           });
             }
         }
@@ -309,21 +1058,111 @@ fn parse_set_header(data: &[u8]) -> nom::IResult<&[u8], SetHeader> {
     // |          Set ID               |          Length               |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-    named!(set_id <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(set_length <&[u8], u16>, map!(take!(2), be_buf_to_u16));
+    named!(set_id <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(set_length <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
 
-    named!(set_header <&[u8], SetHeader>, chain!(
-        id: set_id ~
-        length: set_length,
-        || { SetHeader {
+    named!(set_header <&[u8], SetHeader>, do_parse!(
+        id: set_id >>
+        length: set_length >>
+        (SetHeader {
             set_id: id,
-            length: length
-        }}
+            length
+        })
     ));
 
     set_header(data)
 }
 
+// The message header, once its version and length are checked against the message
+#[inline]
+pub(crate) fn parse_message_header(data: &[u8]) -> Result<(&[u8], IpfixHeader), IpfixError> {
+    let (remaining, header) = match parse_ipfix_header(data) {
+        nom::IResult::Done(bytes, header) => (bytes, header),
+        _ => {
+            return Err(IpfixError::TruncatedHeader {
+                offset: 0,
+                set_id: None,
+                length: IPFIX_HEADER_LENGTH,
+                available: data.len(),
+            })
+        }
+    };
+    if header.version != IPFIX_VERSION {
+        return Err(IpfixError::BadVersion {
+            offset: 0,
+            set_id: None,
+            version: header.version,
+        });
+    }
+    if header.length as usize != data.len() {
+        return Err(IpfixError::LengthMismatch {
+            offset: 2,
+            set_id: None,
+            expected: header.length as usize,
+            actual: data.len(),
+        });
+    }
+    Ok((remaining, header))
+}
+
+// The sets in the rest of a message after its header, each with its offset in the message and
+// the bytes after its set header. Stops after the first set that doesn't fit.
+pub(crate) struct MessageSets<'a> {
+    message_length: usize,
+    remaining: &'a [u8],
+}
+
+impl<'a> MessageSets<'a> {
+    pub(crate) fn new(data: &'a [u8], remaining: &'a [u8]) -> MessageSets<'a> {
+        MessageSets {
+            message_length: data.len(),
+            remaining,
+        }
+    }
+}
+
+impl<'a> Iterator for MessageSets<'a> {
+    type Item = Result<(usize, SetHeader, &'a [u8]), IpfixError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let remaining = ::std::mem::take(&mut self.remaining);
+        let offset = self.message_length - remaining.len();
+        let (bytes, set_header) = match parse_set_header(remaining) {
+            nom::IResult::Done(bytes, set_header) => (bytes, set_header),
+            _ => {
+                return Some(Err(IpfixError::TruncatedSet {
+                    offset,
+                    set_id: None,
+                    length: SET_HEADER_LENGTH,
+                    available: remaining.len(),
+                }))
+            }
+        };
+        if (set_header.length as usize) < SET_HEADER_LENGTH {
+            return Some(Err(IpfixError::ShortSetLength {
+                offset,
+                set_id: Some(set_header.set_id),
+                length: set_header.length,
+            }));
+        }
+        let set_length = set_header.length as usize - SET_HEADER_LENGTH;
+        if set_length > bytes.len() {
+            return Some(Err(IpfixError::TruncatedSet {
+                offset,
+                set_id: Some(set_header.set_id),
+                length: set_header.length as usize,
+                available: remaining.len(),
+            }));
+        }
+        let (set, rest) = bytes.split_at(set_length);
+        self.remaining = rest;
+        Some(Ok((offset, set_header, set)))
+    }
+}
+
 #[inline]
 fn parse_template_header(data: &[u8]) -> nom::IResult<&[u8], TemplateHeader> {
     // 0                   1                   2                   3
@@ -332,23 +1171,26 @@ fn parse_template_header(data: &[u8]) -> nom::IResult<&[u8], TemplateHeader> {
     // |      Template ID (> 255)      |         Field Count           |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-    named!(template_id <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(field_count <&[u8], u16>, map!(take!(2), be_buf_to_u16));
+    named!(template_id <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(field_count <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
 
-    named!(template_header <&[u8], TemplateHeader>, chain!(
-        id: template_id ~
-        field_count: field_count,
-        || { TemplateHeader {
+    named!(template_header <&[u8], TemplateHeader>, do_parse!(
+        id: template_id >>
+        field_count: field_count >>
+        (TemplateHeader {
             template_id: id,
-            field_count: field_count
-        }}
+            field_count
+        })
     ));
 
     template_header(data)
 }
 
 #[inline]
-fn parse_template_set(mut data: &[u8], set_header: SetHeader) -> nom::IResult<&[u8], Set> {
+fn parse_template_set(data: &[u8],
+                      set_header: SetHeader,
+                      offset: usize)
+                      -> Result<TemplateSet, IpfixError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -382,30 +1224,46 @@ fn parse_template_set(mut data: &[u8], set_header: SetHeader) -> nom::IResult<&[
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
     let mut templates = Vec::<Template>::new();
-
-    while data.len() > 0 as usize {
-        let mut fields = Vec::<FieldSpecifier>::new();
-        if let nom::IResult::Done(bytes, header) = parse_template_header(&data) {
-            data = bytes;
-            for _ in 0..header.field_count {
-                if let nom::IResult::Done(bytes, field) = parse_field_specifier(data) {
-                    fields.push(field);
-                    data = bytes;
+    let mut remaining = data;
+
+    // anything too short to hold another template header is padding
+    while let nom::IResult::Done(bytes, header) = parse_template_header(remaining) {
+        let (bytes, fields) = parse_field_specifiers(bytes, header.field_count)
+            .map_err(|rest| {
+                IpfixError::MalformedFieldSpecifier {
+                    offset: offset + SET_HEADER_LENGTH + data.len() - rest.len(),
+                    set_id: Some(set_header.set_id),
                 }
-            }
-            templates.push(Template {
-                header: header,
-                field_specifiers: fields,
-            });
-        }
+            })?;
+        remaining = bytes;
+        templates.push(Template {
+            header,
+            field_specifiers: fields,
+            received: Instant::now(),
+        });
     }
 
-    let result = Set::TemplateSet(TemplateSet {
+    Ok(TemplateSet {
         header: set_header,
         records: templates,
-    });
+    })
+}
 
-    nom::IResult::Done(data, result)
+#[inline]
+fn parse_field_specifiers(mut data: &[u8],
+                          count: u16)
+                          -> Result<(&[u8], Vec<FieldSpecifier>), &[u8]> {
+    // on failure, hands back the bytes at the field specifier that could not be parsed
+    let mut fields = Vec::<FieldSpecifier>::with_capacity(count as usize);
+    for _ in 0..count {
+        if let nom::IResult::Done(bytes, field) = parse_field_specifier(data) {
+            fields.push(field);
+            data = bytes;
+        } else {
+            return Err(data);
+        }
+    }
+    Ok((data, fields))
 }
 
 #[inline]
@@ -417,27 +1275,32 @@ fn parse_options_template_header(data: &[u8]) -> nom::IResult<&[u8], OptionsTemp
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
     // |      Scope Field Count        |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-
-    named!(template_id <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(field_count <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-    named!(scope_field_count <&[u8], u16>, map!(take!(2), be_buf_to_u16));
-
-    named!(options_template_header <&[u8], OptionsTemplateHeader>, chain!(
-        id: template_id ~
-        field_count: field_count ~
-        scope_field_count: scope_field_count,
-        || { OptionsTemplateHeader {
-            id: id,
-            field_count: field_count,
-            scope_field_count: scope_field_count
-        }}
+    //
+    // Withdrawal records have a Field Count of 0 and no Scope Field Count
+
+    named!(template_id <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(field_count <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+    named!(scope_field_count <&[u8], u16>, map_opt!(take!(2), be_buf_to_u16));
+
+    named!(options_template_header <&[u8], OptionsTemplateHeader>, do_parse!(
+        id: template_id >>
+        field_count: field_count >>
+        scope_field_count: cond_with_error!(field_count > 0, scope_field_count) >>
+        (OptionsTemplateHeader {
+            id,
+            field_count,
+            scope_field_count: scope_field_count.unwrap_or(0)
+        })
     ));
 
     options_template_header(data)
 }
 
 #[inline]
-fn parse_options_template_set(mut data: &[u8], set_header: SetHeader) -> nom::IResult<&[u8], Set> {
+fn parse_options_template_set(data: &[u8],
+                              set_header: SetHeader,
+                              offset: usize)
+                              -> Result<OptionsTemplateSet, IpfixError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -467,37 +1330,46 @@ fn parse_options_template_set(mut data: &[u8], set_header: SetHeader) -> nom::IR
     //TODO: merge this into parse_template_set
 
     let mut templates = Vec::<OptionsTemplate>::new();
-
-    while data.len() > 0 as usize {
-        let mut fields = Vec::<FieldSpecifier>::new();
-        if let nom::IResult::Done(bytes, header) = parse_options_template_header(&data) {
-            data = bytes;
-            for _ in 0..header.field_count {
-                if let nom::IResult::Done(bytes, field) = parse_field_specifier(data) {
-                    fields.push(field);
-                    data = bytes;
-                }
-            }
-            templates.push(OptionsTemplate {
-                header: header,
-                field_specifiers: fields,
+    let mut remaining = data;
+
+    // anything too short to hold another options template header is padding
+    while let nom::IResult::Done(bytes, header) = parse_options_template_header(remaining) {
+        // RFC 7011 §3.4.2.2: the scope field count of an options template (that isn't a
+        // withdrawal) MUST NOT be zero
+        if header.field_count > 0 && header.scope_field_count == 0 {
+            return Err(IpfixError::MalformedFieldSpecifier {
+                offset: offset + SET_HEADER_LENGTH + data.len() - bytes.len() - 2,
+                set_id: Some(set_header.set_id),
             });
         }
+        let (bytes, fields) = parse_field_specifiers(bytes, header.field_count)
+            .map_err(|rest| {
+                IpfixError::MalformedFieldSpecifier {
+                    offset: offset + SET_HEADER_LENGTH + data.len() - rest.len(),
+                    set_id: Some(set_header.set_id),
+                }
+            })?;
+        remaining = bytes;
+        templates.push(OptionsTemplate {
+            header,
+            field_specifiers: fields,
+            received: Instant::now(),
+        });
     }
 
-    let result = Set::OptionsTemplateSet(OptionsTemplateSet {
+    Ok(OptionsTemplateSet {
         header: set_header,
         records: templates,
-    });
-
-    nom::IResult::Done(data, result)
+    })
 }
 
 #[inline]
 fn parse_data_set<'a>(data: &'a [u8],
                       set_header: SetHeader,
-                      template: &Template)
-                      -> nom::IResult<&'a [u8], Set<'a>> {
+                      template: &Template,
+                      scope: &TemplateScope,
+                      offset: usize)
+                      -> Result<DataSet<'a>, IpfixError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -516,31 +1388,22 @@ fn parse_data_set<'a>(data: &'a [u8],
     // |              ...              |      Padding (optional)       |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-    let mut records = Vec::<DataRecord>::new();
-    let mut offset = 0;
-
-    while offset < data.len() {
-        let mut values = BTreeMap::<u16, &[u8]>::new();
-        for field in &template.field_specifiers {
-            let bytes = &data[offset..offset + field.field_length as usize];
-            values.insert(field.ident, bytes);
-            offset += field.field_length as usize;
-        }
-        records.push(DataRecord { values: values });
-    }
-
-    nom::IResult::Done(data,
-                       Set::DataSet(DataSet {
-                           header: set_header,
-                           records: records,
-                       }))
+    let records = parse_data_records(data, &template.field_specifiers, scope)
+        .map_err(|truncated| truncated.error(offset, set_header.set_id))?;
+    Ok(DataSet {
+        header: set_header,
+        records,
+        template_kind: TemplateKind::Template,
+    })
 }
 
 #[inline]
 fn parse_options_set<'a>(data: &'a [u8],
                          set_header: SetHeader,
-                         template: &OptionsTemplate)
-                         -> nom::IResult<&'a [u8], Set<'a>> {
+                         template: &OptionsTemplate,
+                         scope: &TemplateScope,
+                         offset: usize)
+                         -> Result<DataSet<'a>, IpfixError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -559,22 +1422,152 @@ fn parse_options_set<'a>(data: &'a [u8],
     // |              ...              |      Padding (optional)       |
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
+    let records = parse_data_records(data, &template.field_specifiers, scope)
+        .map_err(|truncated| truncated.error(offset, set_header.set_id))?;
+    Ok(DataSet {
+        header: set_header,
+        records,
+        template_kind: TemplateKind::OptionsTemplate {
+            scope_field_count: template.header.scope_field_count,
+        },
+    })
+}
+
+// A record that claims more bytes than are left in its set: `length` bytes from `offset` in the
+// set, of which `available` are there
+struct TruncatedRecord {
+    offset: usize,
+    length: usize,
+    available: usize,
+}
+
+impl TruncatedRecord {
+    // the error for a data set at `offset` in its message
+    fn error(&self, offset: usize, set_id: u16) -> IpfixError {
+        IpfixError::TruncatedSet {
+            offset: offset + SET_HEADER_LENGTH + self.offset,
+            set_id: Some(set_id),
+            length: self.length,
+            available: self.available,
+        }
+    }
+}
+
+#[inline]
+fn parse_data_records<'a>(data: &'a [u8],
+                          fields: &[FieldSpecifier],
+                          scope: &TemplateScope)
+                          -> Result<Vec<DataRecord<'a>>, TruncatedRecord> {
+    // variable-length fields take at least their one byte length prefix
+    let min_record_length = fields.iter().fold(0, |sum, field| {
+        sum +
+        if field.field_length == VARIABLE_LENGTH {
+            1
+        } else {
+            field.field_length as usize
+        }
+    });
     let mut records = Vec::<DataRecord>::new();
-    let mut offset = 0;
 
-    while offset < data.len() {
-        let mut values = BTreeMap::<u16, &[u8]>::new();
-        for field in &template.field_specifiers {
-            let bytes = &data[offset..offset + field.field_length as usize];
-            values.insert(field.ident, bytes);
-            offset += field.field_length as usize;
+    // a template whose fields take up no space would never advance through the set
+    if min_record_length == 0 {
+        return Ok(records);
+    }
+    let list_types = list::list_types(fields, scope.registry);
+    let variable_length = fields.iter().any(|field| field.field_length == VARIABLE_LENGTH);
+
+    // whatever is left over once not even the shortest record fits is padding, but a record
+    // whose variable-length fields run past the end of the set was cut short
+    let mut remaining = data;
+    while remaining.len() >= min_record_length {
+        // records with a variable-length field can be as short as the padding that aligns the
+        // end of the set, so a few zero bytes are taken to be padding rather than empty records
+        if variable_length && remaining.len() < SET_ALIGNMENT &&
+           remaining.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        match parse_data_record(remaining, fields, &list_types, scope) {
+            Ok((rest, record)) => {
+                records.push(record);
+                remaining = rest;
+            }
+            Err(length) => {
+                return Err(TruncatedRecord {
+                    offset: data.len() - remaining.len(),
+                    length,
+                    available: remaining.len(),
+                })
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+// On failure, returns the bytes the record needs up to the end of the field that runs past the
+// end of `data`
+#[inline]
+fn parse_data_record<'a>(mut data: &'a [u8],
+                         fields: &[FieldSpecifier],
+                         list_types: &[Option<DataType>],
+                         scope: &TemplateScope)
+                         -> Result<(&'a [u8], DataRecord<'a>), usize> {
+    let length = data.len();
+    let mut values = Vec::<(FieldSpecifier, Cow<[u8]>)>::with_capacity(fields.len());
+    let mut lists = Vec::new();
+    for (index, (field, &list_type)) in fields.iter().zip(list_types).enumerate() {
+        let (bytes, rest) = parse_field_value(data, field.field_length)
+            .map_err(|needed| length - data.len() + needed)?;
+        if let Some(list_type) = list_type {
+            if let Some(list) = list::decode_list(list_type, bytes, scope) {
+                lists.push((index, list));
+            }
         }
-        records.push(DataRecord { values: values });
+        values.push((*field, Cow::Borrowed(bytes)));
+        data = rest;
     }
+    Ok((data,
+        DataRecord {
+            fields: values,
+            lists,
+        }))
+}
 
-    nom::IResult::Done(data,
-                       Set::DataSet(DataSet {
-                           header: set_header,
-                           records: records,
-                       }))
+// On failure, returns the bytes the field needs
+#[inline]
+fn parse_field_value(data: &[u8], field_length: u16) -> Result<(&[u8], &[u8]), usize> {
+    // Variable-length fields are prefixed by their length, in one byte when it is below 255:
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // | Length (< 255)|          Information Element                  |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                      ... continuing as needed                 |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // and otherwise by 255 followed by the length in two bytes:
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |      255      |      Length (0 to 65535)      |       IE      |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                      ... continuing as needed                 |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    let (prefix, length) = if field_length == VARIABLE_LENGTH {
+        match data.first() {
+            Some(&length) if length < 255 => (1, length as usize),
+            Some(_) => (3, data.get(1..3).and_then(be_buf_to_u16).ok_or(3usize)? as usize),
+            None => return Err(1),
+        }
+    } else {
+        (0, field_length as usize)
+    };
+
+    let data = &data[prefix..];
+    if data.len() < length {
+        // the record was cut short
+        return Err(prefix + length);
+    }
+    Ok(data.split_at(length))
 }
@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+
+use super::conversions::*;
+use super::registry::{DataType, Registry};
+use super::{parse_data_records, parse_field_specifier, parse_field_value, DataRecord,
+            FieldSpecifier, TemplateScope};
+
+// The structured data types of RFC 6313 put lists inside a record, as variable-length fields.
+// Each starts with the semantic of the list, followed by
+//
+// basicList: the field specifier of its element, then its values:
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |    Semantic   |E|    Field ID                 |   Element... |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// | ...Length     |           Enterprise Number ...                |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |      ...      |              basicList Content ...            |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// subTemplateList: the id of a template, then records of it:
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |   Semantic    |         Template ID           |     ...       |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                subTemplateList Content    ...                 |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// subTemplateMultiList: any number of groups of records, each with its template id and length:
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |  Semantic     |         Template ID X         |Data Records...|
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// | ... Length X  |     Data Record X.1 Content ...               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// A list is decoded with the templates of the session and Observation Domain its record came
+// from. A list that can't be decoded in full, because it refers to a template that isn't known
+// or is malformed, is left as the octet array it arrived as.
+
+// template id and length of each group of a subTemplateMultiList
+const MULTI_LIST_HEADER_LENGTH: usize = 4;
+
+/// The semantic of a structured list (RFC 6313 §4.4): how its elements relate to the record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ListSemantic {
+    NoneOf,
+    ExactlyOneOf,
+    OneOrMoreOf,
+    AllOf,
+    Ordered,
+    Undefined,
+    /// A value IANA hasn't assigned.
+    Unassigned(u8),
+}
+
+impl ListSemantic {
+    /// The name of the semantic in RFC 6313, e.g. "allOf".
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match *self {
+            ListSemantic::NoneOf => "noneOf",
+            ListSemantic::ExactlyOneOf => "exactlyOneOf",
+            ListSemantic::OneOrMoreOf => "oneOrMoreOf",
+            ListSemantic::AllOf => "allOf",
+            ListSemantic::Ordered => "ordered",
+            ListSemantic::Undefined => "undefined",
+            ListSemantic::Unassigned(_) => return None,
+        };
+        Some(name)
+    }
+}
+
+impl From<u8> for ListSemantic {
+    fn from(value: u8) -> ListSemantic {
+        match value {
+            0 => ListSemantic::NoneOf,
+            1 => ListSemantic::ExactlyOneOf,
+            2 => ListSemantic::OneOrMoreOf,
+            3 => ListSemantic::AllOf,
+            4 => ListSemantic::Ordered,
+            255 => ListSemantic::Undefined,
+            value => ListSemantic::Unassigned(value),
+        }
+    }
+}
+
+/// The value of a basicList, subTemplateList or subTemplateMultiList field, as returned by
+/// `DataRecord::list`.
+#[derive(PartialEq)]
+pub enum StructuredList<'a> {
+    /// Values of one Information Element, as the fields of a record with `field` repeated once
+    /// for every value.
+    Basic {
+        semantic: ListSemantic,
+        field: FieldSpecifier,
+        values: DataRecord<'a>,
+    },
+    /// Records of one template.
+    SubTemplate {
+        semantic: ListSemantic,
+        template_id: u16,
+        records: Vec<DataRecord<'a>>,
+    },
+    /// Groups of records, each of its own template.
+    SubTemplateMulti {
+        semantic: ListSemantic,
+        lists: Vec<(u16, Vec<DataRecord<'a>>)>,
+    },
+}
+
+impl<'a> StructuredList<'a> {
+    pub fn semantic(&self) -> ListSemantic {
+        match *self {
+            StructuredList::Basic { semantic, .. } |
+            StructuredList::SubTemplate { semantic, .. } |
+            StructuredList::SubTemplateMulti { semantic, .. } => semantic,
+        }
+    }
+
+    pub fn into_owned(self) -> StructuredList<'static> {
+        let owned = |records: Vec<DataRecord<'a>>| {
+            records.into_iter().map(DataRecord::into_owned).collect()
+        };
+        match self {
+            StructuredList::Basic { semantic, field, values } => {
+                StructuredList::Basic {
+                    semantic,
+                    field,
+                    values: values.into_owned(),
+                }
+            }
+            StructuredList::SubTemplate { semantic, template_id, records } => {
+                StructuredList::SubTemplate {
+                    semantic,
+                    template_id,
+                    records: owned(records),
+                }
+            }
+            StructuredList::SubTemplateMulti { semantic, lists } => {
+                StructuredList::SubTemplateMulti {
+                    semantic,
+                    lists: lists.into_iter().map(|(id, records)| (id, owned(records))).collect(),
+                }
+            }
+        }
+    }
+}
+
+// The list type of each field, for the ones `registry` says hold a structured list
+pub(crate) fn list_types(fields: &[FieldSpecifier],
+                         registry: &Registry)
+                         -> Vec<Option<DataType>> {
+    fields.iter()
+        .map(|field| {
+            registry.get(field.element_id()).map(|ie| ie.data_type).filter(|&data_type| {
+                data_type == DataType::BasicList || data_type == DataType::SubTemplateList ||
+                data_type == DataType::SubTemplateMultiList
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn decode_list<'a>(data_type: DataType,
+                              data: &'a [u8],
+                              scope: &TemplateScope)
+                              -> Option<StructuredList<'a>> {
+    let scope = scope.nested()?;
+    let (&semantic, data) = data.split_first()?;
+    let semantic = ListSemantic::from(semantic);
+    match data_type {
+        DataType::BasicList => decode_basic_list(semantic, data, &scope),
+        DataType::SubTemplateList => {
+            let template_id = be_buf_to_u16(data)?;
+            let fields = scope.fields(template_id)?;
+            Some(StructuredList::SubTemplate {
+                semantic,
+                template_id,
+                records: parse_data_records(&data[2..], fields, &scope).ok()?,
+            })
+        }
+        DataType::SubTemplateMultiList => {
+            let mut lists = Vec::new();
+            let mut remaining = data;
+            while !remaining.is_empty() {
+                let template_id = be_buf_to_u16(remaining)?;
+                let length = remaining.get(2..4).and_then(be_buf_to_u16)? as usize;
+                if length < MULTI_LIST_HEADER_LENGTH || length > remaining.len() {
+                    return None;
+                }
+                let fields = scope.fields(template_id)?;
+                let records = &remaining[MULTI_LIST_HEADER_LENGTH..length];
+                lists.push((template_id, parse_data_records(records, fields, &scope).ok()?));
+                remaining = &remaining[length..];
+            }
+            Some(StructuredList::SubTemplateMulti { semantic, lists })
+        }
+        _ => None,
+    }
+}
+
+fn decode_basic_list<'a>(semantic: ListSemantic,
+                         data: &'a [u8],
+                         scope: &TemplateScope)
+                         -> Option<StructuredList<'a>> {
+    let (mut remaining, field) = match parse_field_specifier(data) {
+        nom::IResult::Done(bytes, field) => (bytes, field),
+        _ => return None,
+    };
+    // elements that take up no space would never advance through the list
+    if field.field_length == 0 {
+        return None;
+    }
+    let list_type = list_types(&[field], scope.registry)[0];
+
+    let mut fields = Vec::new();
+    let mut lists = Vec::new();
+    while !remaining.is_empty() {
+        let (value, rest) = parse_field_value(remaining, field.field_length).ok()?;
+        if let Some(data_type) = list_type {
+            lists.push((fields.len(), decode_list(data_type, value, scope)?));
+        }
+        fields.push((field, Cow::Borrowed(value)));
+        remaining = rest;
+    }
+    Some(StructuredList::Basic {
+        semantic,
+        field,
+        values: DataRecord { fields, lists },
+    })
+}
@@ -0,0 +1,376 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::time::Instant;
+
+use super::conversions::*;
+use super::{parse_set_header, DataRecord, DataSet, FieldSpecifier, IpfixError, IpfixHeader,
+            OptionsTemplate, OptionsTemplateHeader, OptionsTemplateSet, SetHeader, Template,
+            TemplateHeader, TemplateKind, TemplateSet, SET_HEADER_LENGTH};
+
+// NetFlow v9 (RFC 3954) is what IPFIX grew out of: its FlowSets are IPFIX sets with template
+// FlowSet ids 0 and 1 instead of 2 and 3, and field types without an enterprise bit. Packets are
+// decoded into the same templates and data sets, with the packet header mapped onto an IPFIX
+// message header.
+//
+// NetFlow v5 has no templates, only records of one fixed format. Their fields are mapped onto the
+// Information Elements with the same meaning, so they decode like the fields of any data set.
+
+pub(crate) const NETFLOW_V9_VERSION: u16 = 9;
+pub(crate) const NETFLOW_V9_HEADER_LENGTH: usize = 20;
+pub(crate) const TEMPLATE_FLOWSET_ID: u16 = 0;
+pub(crate) const OPTIONS_TEMPLATE_FLOWSET_ID: u16 = 1;
+
+pub(crate) const NETFLOW_V5_VERSION: u16 = 5;
+const NETFLOW_V5_HEADER_LENGTH: usize = 24;
+const NETFLOW_V5_RECORD_LENGTH: usize = 48;
+// the id NetFlow v5 records get as their template id, as they have no template
+pub(crate) const NETFLOW_V5_TEMPLATE_ID: u16 = 0;
+
+// (offset, length, Information Element) of the fields of a NetFlow v5 record, leaving out the
+// padding
+const NETFLOW_V5_FIELDS: [(usize, u16, u16); 18] = [
+    (0, 4, 8), // srcaddr: sourceIPv4Address
+    (4, 4, 12), // dstaddr: destinationIPv4Address
+    (8, 4, 15), // nexthop: ipNextHopIPv4Address
+    (12, 2, 10), // input: ingressInterface
+    (14, 2, 14), // output: egressInterface
+    (16, 4, 2), // dPkts: packetDeltaCount
+    (20, 4, 1), // dOctets: octetDeltaCount
+    (24, 4, 22), // First: flowStartSysUpTime
+    (28, 4, 21), // Last: flowEndSysUpTime
+    (32, 2, 7), // srcport: sourceTransportPort
+    (34, 2, 11), // dstport: destinationTransportPort
+    (37, 1, 6), // tcp_flags: tcpControlBits
+    (38, 1, 4), // prot: protocolIdentifier
+    (39, 1, 5), // tos: ipClassOfService
+    (40, 2, 16), // src_as: bgpSourceAsNumber
+    (42, 2, 17), // dst_as: bgpDestinationAsNumber
+    (44, 1, 9), // src_mask: sourceIPv4PrefixLength
+    (45, 1, 13), // dst_mask: destinationIPv4PrefixLength
+];
+// samplingInterval, added to every v5 record from the packet header, without the sampling mode
+const SAMPLING_INTERVAL: u16 = 34;
+const SAMPLING_INTERVAL_MASK: u16 = 0x3FFF;
+
+// Field types from 32768 up are vendor-specific (RFC 3954 §8). As IPFIX has no room for them in
+// an element id, they become elements of Cisco's Private Enterprise Number with the top bit
+// cleared, the way Cisco numbers them in IPFIX: type 45003 is element 12235 of enterprise 9.
+const VENDOR_ENTERPRISE_NUMBER: u32 = 9;
+const VENDOR_TYPE_BIT: u16 = 0x8000;
+
+const OPTIONS_TEMPLATE_HEADER_LENGTH: usize = 6;
+const FIELD_SPECIFIER_LENGTH: usize = 4;
+
+// scope field types (RFC 3954 §6.1)
+const SCOPE_SYSTEM: u16 = 1;
+const SCOPE_INTERFACE: u16 = 2;
+const SCOPE_LINE_CARD: u16 = 3;
+const SCOPE_CACHE: u16 = 4;
+const SCOPE_TEMPLATE: u16 = 5;
+
+/// Reads a packet header, returning the rest of the packet, the header as an IPFIX message
+/// header, and the exporter's uptime in milliseconds.
+pub(crate) fn parse_header(data: &[u8]) -> Result<(&[u8], IpfixHeader, u32), IpfixError> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |       Version Number          |            Count              |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                           sysUpTime                           |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                           UNIX Secs                           |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                       Sequence Number                         |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                        Source ID                              |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // Count is the number of records in the packet, which exporters don't all agree on, so it
+    // is not checked. There is no length: a packet is a whole datagram.
+
+    if data.len() < NETFLOW_V9_HEADER_LENGTH {
+        return Err(IpfixError::TruncatedHeader {
+            offset: 0,
+            set_id: None,
+            length: NETFLOW_V9_HEADER_LENGTH,
+            available: data.len(),
+        });
+    }
+    if data.len() > u16::MAX as usize {
+        return Err(IpfixError::LengthMismatch {
+            offset: 0,
+            set_id: None,
+            expected: u16::MAX as usize,
+            actual: data.len(),
+        });
+    }
+    let u32_at = |offset: usize| be_buf_to_u32(&data[offset..offset + 4]).unwrap_or(0);
+    let header = IpfixHeader {
+        version: NETFLOW_V9_VERSION,
+        length: data.len() as u16,
+        export_time: u32_at(8),
+        sequence_number: u32_at(12),
+        observation_domain_id: u32_at(16),
+    };
+    Ok((&data[NETFLOW_V9_HEADER_LENGTH..], header, u32_at(4)))
+}
+
+pub(crate) fn parse_template_flowset(data: &[u8],
+                                     set_header: SetHeader,
+                                     offset: usize)
+                                     -> Result<TemplateSet, IpfixError> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |      Template ID (> 255)      |         Field Count           |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |        Field Type 1           |         Field Length 1        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |             ...               |              ...              |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |        Field Type N           |         Field Length N        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                          Padding (opt)                        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    let mut templates = Vec::<Template>::new();
+    let mut remaining = data;
+
+    // a template header is laid out like a set header, and anything too short to hold one is
+    // padding
+    while let nom::IResult::Done(bytes, header) = parse_set_header(remaining) {
+        let field_count = header.length as usize;
+        let length = field_count * FIELD_SPECIFIER_LENGTH;
+        if bytes.len() < length {
+            return Err(IpfixError::MalformedFieldSpecifier {
+                offset: offset + SET_HEADER_LENGTH + data.len() - bytes.len(),
+                set_id: Some(set_header.set_id),
+            });
+        }
+        remaining = &bytes[length..];
+        // NetFlow v9 has no withdrawals, so a template without fields, which IPFIX would take
+        // as one, is skipped
+        if field_count == 0 {
+            continue;
+        }
+        templates.push(Template {
+            header: TemplateHeader {
+                template_id: header.set_id,
+                field_count: header.length,
+            },
+            field_specifiers: parse_fields(&bytes[..length], None),
+            received: Instant::now(),
+        });
+    }
+
+    Ok(TemplateSet {
+        header: set_header,
+        records: templates,
+    })
+}
+
+pub(crate) fn parse_options_template_flowset(data: &[u8],
+                                             set_header: SetHeader,
+                                             offset: usize)
+                                             -> Result<OptionsTemplateSet, IpfixError> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |      Template ID (> 255)      |      Option Scope Length      |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |        Option Length          |       Scope 1 Field Type      |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |     Scope 1 Field Length      |               ...             |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |     Scope N Field Length      |      Option 1 Field Type      |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |     Option 1 Field Length     |             ...               |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |     Option M Field Length     |           Padding             |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // The scope and option lengths are in bytes, not fields.
+
+    let mut templates = Vec::<OptionsTemplate>::new();
+    let mut remaining = data;
+
+    // anything too short to hold another options template header is padding
+    while remaining.len() >= OPTIONS_TEMPLATE_HEADER_LENGTH {
+        let u16_at = |offset: usize| be_buf_to_u16(&remaining[offset..offset + 2]).unwrap_or(0);
+        let (id, scope_length, option_length) = (u16_at(0), u16_at(2), u16_at(4));
+        let bytes = &remaining[OPTIONS_TEMPLATE_HEADER_LENGTH..];
+        let length = scope_length as usize + option_length as usize;
+        if !(scope_length as usize).is_multiple_of(FIELD_SPECIFIER_LENGTH) ||
+           !(option_length as usize).is_multiple_of(FIELD_SPECIFIER_LENGTH) ||
+           bytes.len() < length {
+            return Err(IpfixError::MalformedFieldSpecifier {
+                offset: offset + SET_HEADER_LENGTH + data.len() - bytes.len(),
+                set_id: Some(set_header.set_id),
+            });
+        }
+        // a template with neither is padding, as a withdrawal would be in IPFIX
+        if length == 0 {
+            break;
+        }
+
+        let (scope, options) = bytes[..length].split_at(scope_length as usize);
+        let mut fields = parse_fields(scope, Some(scope_element));
+        fields.extend(parse_fields(options, None));
+        remaining = &bytes[length..];
+        templates.push(OptionsTemplate {
+            header: OptionsTemplateHeader {
+                id,
+                field_count: fields.len() as u16,
+                scope_field_count: (scope_length as usize / FIELD_SPECIFIER_LENGTH) as u16,
+            },
+            field_specifiers: fields,
+            received: Instant::now(),
+        });
+    }
+
+    Ok(OptionsTemplateSet {
+        header: set_header,
+        records: templates,
+    })
+}
+
+// Field types and lengths, 4 bytes each. NetFlow v9 field types share their numbers with IANA
+// Information Elements, and vendors use the whole 16 bits rather than an enterprise bit: their
+// types are mapped to VENDOR_ENTERPRISE_NUMBER.
+fn parse_fields(data: &[u8], map_type: Option<fn(u16, u16) -> u16>) -> Vec<FieldSpecifier> {
+    data.chunks(FIELD_SPECIFIER_LENGTH)
+        .map(|field| {
+            let field_type = be_buf_to_u16(&field[..2]).unwrap_or(0);
+            let field_length = be_buf_to_u16(&field[2..]).unwrap_or(0);
+            let field_type = map_type.map_or(field_type, |map| map(field_type, field_length));
+            if field_type & VENDOR_TYPE_BIT != 0 {
+                FieldSpecifier {
+                    ident: field_type & !VENDOR_TYPE_BIT,
+                    field_length,
+                    enterprise_number: Some(VENDOR_ENTERPRISE_NUMBER),
+                }
+            } else {
+                FieldSpecifier {
+                    ident: field_type,
+                    field_length,
+                    enterprise_number: None,
+                }
+            }
+        })
+        .collect()
+}
+
+// The Information Element for a scope field type. Types RFC 3954 doesn't define are kept as
+// they are.
+fn scope_element(scope_type: u16, field_length: u16) -> u16 {
+    match scope_type {
+        // the exporter's address
+        SCOPE_SYSTEM if field_length == 16 => 131, // exporterIPv6Address
+        SCOPE_SYSTEM => 130, // exporterIPv4Address
+        SCOPE_INTERFACE => 10, // ingressInterface
+        SCOPE_LINE_CARD => 141, // lineCardId
+        SCOPE_CACHE => 143, // meteringProcessId
+        SCOPE_TEMPLATE => 145, // templateId
+        other => other,
+    }
+}
+
+/// Decodes a NetFlow v5 packet, returning its header as an IPFIX message header, the exporter's
+/// uptime in milliseconds, and its records in a data set of their own, each with the sampling
+/// interval of the packet.
+pub(crate) fn parse_v5_packet(data: &[u8])
+                              -> Result<(IpfixHeader, u32, DataSet<'_>), IpfixError> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |       Version Number          |            Count              |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                           SysUptime                           |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                           UNIX Secs                           |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                           UNIX Nsecs                          |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                         Flow Sequence                         |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |  Engine Type  |   Engine ID   |      Sampling Interval        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // The engine type and id together stand in for the Observation Domain id. Flow Sequence
+    // counts records, like the IPFIX sequence number. The top 2 bits of Sampling Interval are
+    // the sampling mode, and the interval is in the other 14.
+
+    if data.len() < NETFLOW_V5_HEADER_LENGTH {
+        return Err(IpfixError::TruncatedHeader {
+            offset: 0,
+            set_id: None,
+            length: NETFLOW_V5_HEADER_LENGTH,
+            available: data.len(),
+        });
+    }
+    // too long for the length of a message header, whatever the count says
+    let message_length = u16::try_from(data.len()).map_err(|_| {
+        IpfixError::InvalidMessageLength {
+            offset: 2,
+            set_id: None,
+            length: data.len(),
+        }
+    })?;
+    let count = be_buf_to_u16(&data[2..4]).unwrap_or(0) as usize;
+    let length = NETFLOW_V5_HEADER_LENGTH + count * NETFLOW_V5_RECORD_LENGTH;
+    if length != data.len() {
+        return Err(IpfixError::LengthMismatch {
+            offset: 2,
+            set_id: None,
+            expected: length,
+            actual: data.len(),
+        });
+    }
+
+    let u32_at = |offset: usize| be_buf_to_u32(&data[offset..offset + 4]).unwrap_or(0);
+    let header = IpfixHeader {
+        version: NETFLOW_V5_VERSION,
+        length: message_length,
+        export_time: u32_at(8),
+        sequence_number: u32_at(16),
+        observation_domain_id: be_buf_to_u16(&data[20..22]).unwrap_or(0) as u32,
+    };
+
+    let sampling_interval = be_buf_to_u16(&data[22..24]).unwrap_or(0) & SAMPLING_INTERVAL_MASK;
+    let sampling_field = FieldSpecifier {
+        ident: SAMPLING_INTERVAL,
+        field_length: 2,
+        enterprise_number: None,
+    };
+
+    let records = data[NETFLOW_V5_HEADER_LENGTH..]
+        .chunks(NETFLOW_V5_RECORD_LENGTH)
+        .map(|record| {
+            let mut fields = NETFLOW_V5_FIELDS.iter()
+                .map(|&(offset, length, ident)| {
+                    let field = FieldSpecifier {
+                        ident,
+                        field_length: length,
+                        enterprise_number: None,
+                    };
+                    (field, Cow::Borrowed(&record[offset..offset + length as usize]))
+                })
+                .collect::<Vec<_>>();
+            // from the header, as in the records of an sFlow flow sample
+            fields.push((sampling_field, Cow::Owned(sampling_interval.to_be_bytes().to_vec())));
+            DataRecord {
+                fields,
+                lists: Vec::new(),
+            }
+        })
+        .collect();
+    let set = DataSet {
+        header: SetHeader {
+            set_id: NETFLOW_V5_TEMPLATE_ID,
+            length: message_length - NETFLOW_V5_HEADER_LENGTH as u16,
+        },
+        records,
+        template_kind: TemplateKind::Template,
+    };
+    Ok((header, u32_at(4), set))
+}
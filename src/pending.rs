@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::{IpfixHeader, SessionKey, SetHeader, TemplateKey};
+
+// Data sets that arrived before the template they need, held on to until the template shows up
+// or they get too old. The queue is bounded by the bytes it holds across all templates.
+
+pub(crate) struct PendingSet {
+    pub(crate) header: IpfixHeader,
+    pub(crate) set_header: SetHeader,
+    // of the set in its message
+    pub(crate) offset: usize,
+    pub(crate) data: Vec<u8>,
+    received: Instant,
+}
+
+pub(crate) struct PendingQueue {
+    sets: HashMap<TemplateKey, Vec<PendingSet>>,
+    bytes: usize,
+    max_bytes: usize,
+    max_age: Duration,
+}
+
+impl PendingQueue {
+    pub(crate) fn new(max_bytes: usize, max_age: Duration) -> PendingQueue {
+        PendingQueue {
+            sets: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+            max_age,
+        }
+    }
+
+    // the bytes of the sets that haven't expired, whether or not they were dropped yet
+    pub(crate) fn bytes(&self) -> usize {
+        let max_age = self.max_age;
+        self.sets
+            .values()
+            .flatten()
+            .filter(|set| set.received.elapsed() <= max_age)
+            .fold(0, |sum, set| sum + set.data.len())
+    }
+
+    /// Queue a data set, unless there is no room for it once expired sets are dropped.
+    pub(crate) fn push(&mut self,
+                       key: TemplateKey,
+                       header: IpfixHeader,
+                       set_header: SetHeader,
+                       offset: usize,
+                       data: &[u8])
+                       -> bool {
+        self.expire();
+        if self.bytes + data.len() > self.max_bytes {
+            return false;
+        }
+        self.bytes += data.len();
+        self.sets.entry(key).or_default().push(PendingSet {
+            header,
+            set_header,
+            offset,
+            data: data.to_vec(),
+            received: Instant::now(),
+        });
+        true
+    }
+
+    /// Remove and return the sets waiting on `key` that haven't expired, oldest first.
+    pub(crate) fn take(&mut self, key: &TemplateKey) -> Vec<PendingSet> {
+        self.expire();
+        match self.sets.remove(key) {
+            Some(sets) => {
+                self.bytes -= sets.iter().fold(0, |sum, set| sum + set.data.len());
+                sets
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Drop every set received on `session`.
+    pub(crate) fn remove_session(&mut self, session: SessionKey) {
+        self.sets.retain(|&(s, _, _), _| s != session);
+        self.bytes = self.sets
+            .values()
+            .flatten()
+            .fold(0, |sum, set| sum + set.data.len());
+    }
+
+    fn expire(&mut self) {
+        let max_age = self.max_age;
+        let mut bytes = 0;
+        for sets in self.sets.values_mut() {
+            sets.retain(|set| set.received.elapsed() <= max_age);
+            bytes += sets.iter().fold(0, |sum, set| sum + set.data.len());
+        }
+        self.sets.retain(|_, sets| !sets.is_empty());
+        self.bytes = bytes;
+    }
+}
@@ -1,32 +1,23 @@
 extern crate nom;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use super::conversions;
-use super::DataSet;
+use super::json::{self, ObjectWriter};
+use super::registry::{self, Registry};
+use super::{DataRecord, DataSet, ElementId, FieldValue, ListSemantic, StructuredList};
 
-pub struct IpfixPrinter {
-    formatters: HashMap<u16, (&'static str, fn(&[u8]) -> String)>,
-}
+// renders a value as JSON, or None when the value does not have a length that makes sense for
+// the IE
+type Formatter = fn(&[u8]) -> Option<String>;
 
-#[inline]
-fn be_int(s: &[u8]) -> String {
-    format!("{}",
-            match s.len() {
-                1 => s[0] as u64,
-                2 => conversions::be_buf_to_u16(s) as u64,
-                4 => conversions::be_buf_to_u32(s) as u64,
-                8 => conversions::be_buf_to_u64(s),
-                _ => {
-                    println!("TRIED TO CONVERT {} BYTES TO u64", s.len());
-                    0 as u64
-                }
-            })
-}
-
-#[inline]
-fn ipv4_addr(s: &[u8]) -> String {
-    format!(r#""{}.{}.{}.{}""#, s[0], s[1], s[2], s[3])
+/// Renders data records as JSON, with field names and value types taken from an Information
+/// Element registry. Elements the registry doesn't know are left out.
+pub struct IpfixPrinter {
+    registry: Cow<'static, Registry>,
+    // formatting for elements that have more structure than their abstract data type says
+    formatters: HashMap<ElementId, Formatter>,
 }
 
 #[inline]
@@ -36,7 +27,7 @@ fn ipv6_addr(s: &[u8]) -> String {
 }
 
 #[inline]
-fn mpls_stack(s: &[u8]) -> String {
+fn mpls_stack(s: &[u8]) -> Option<String> {
     //      0                   1                   2
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -57,216 +48,176 @@ fn mpls_stack(s: &[u8]) -> String {
 
     //TODO: do some more conversions here
     if let nom::IResult::Done(_, (label, exp, bottom)) = parse_mpls_stack(s) {
-        format!(r#"{{"label":{},"exp":{},"bottom":{}}}"#,
-                label,
-                exp,
-                bottom == 1)
-            .into()
+        Some(format!(r#"{{"label":{},"exp":{},"bottom":{}}}"#,
+                     label,
+                     exp,
+                     bottom == 1))
     } else {
-        println!("{:?}", parse_mpls_stack(s));
-        println!("MPLS STACK PARSING FAILED");
-        "{}".into()
+        None
     }
 }
 
-#[macro_export]
-macro_rules! field_parser(
-    { $($key:expr => ($string:expr, $value:expr)),+ } => {
-        {
-        let mut m = ::std::collections::HashMap::<u16, (&str, fn(&[u8]) -> String)>::new();
-            $(
-                m.insert($key, ($string, $value));
-            )+
-            m
+#[inline]
+fn since_epoch(time: SystemTime) -> Option<Duration> {
+    time.duration_since(UNIX_EPOCH).ok()
+}
+
+// times are printed as a number of their own unit since the UNIX epoch
+#[inline]
+fn format_value(value: FieldValue) -> Option<String> {
+    let value = match value {
+        FieldValue::Unsigned8(value) => value.to_string(),
+        FieldValue::Unsigned16(value) => value.to_string(),
+        FieldValue::Unsigned32(value) => value.to_string(),
+        FieldValue::Unsigned64(value) => value.to_string(),
+        FieldValue::Signed8(value) => value.to_string(),
+        FieldValue::Signed16(value) => value.to_string(),
+        FieldValue::Signed32(value) => value.to_string(),
+        FieldValue::Signed64(value) => value.to_string(),
+        FieldValue::Float32(value) if value.is_finite() => value.to_string(),
+        FieldValue::Float64(value) if value.is_finite() => value.to_string(),
+        FieldValue::Float32(_) | FieldValue::Float64(_) => return None,
+        FieldValue::Boolean(value) => value.to_string(),
+        FieldValue::MacAddress(s) => {
+            format!(r#""{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}""#,
+                    s[0], s[1], s[2], s[3], s[4], s[5])
         }
+        FieldValue::OctetArray(s) => {
+            let hex = s.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+            format!(r#""{}""#, hex)
+        }
+        FieldValue::String(value) => json::string(&value),
+        FieldValue::DateTimeSeconds(time) => since_epoch(time)?.as_secs().to_string(),
+        FieldValue::DateTimeMilliseconds(time) => since_epoch(time)?.as_millis().to_string(),
+        FieldValue::DateTimeMicroseconds(time) => since_epoch(time)?.as_micros().to_string(),
+        FieldValue::DateTimeNanoseconds(time) => since_epoch(time)?.as_nanos().to_string(),
+        FieldValue::Ipv4Address(address) => format!(r#""{}""#, address),
+        FieldValue::Ipv6Address(address) => ipv6_addr(&address.octets()),
     };
-);
+    Some(value)
+}
+
+// the name of a list semantic, or its number when IANA hasn't named it
+#[inline]
+fn semantic_json(semantic: ListSemantic) -> String {
+    match semantic {
+        ListSemantic::Unassigned(value) => value.to_string(),
+        semantic => json::string(semantic.name().unwrap_or_default()),
+    }
+}
+
+impl Default for IpfixPrinter {
+    fn default() -> IpfixPrinter {
+        IpfixPrinter::new()
+    }
+}
 
 impl IpfixPrinter {
     pub fn new() -> IpfixPrinter {
-        // TODO: handle non-default settings here
-        IpfixPrinter { formatters: IpfixPrinter::get_default_parsers() }
+        IpfixPrinter {
+            registry: Cow::Borrowed(registry::default_registry()),
+            formatters: IpfixPrinter::get_default_formatters(),
+        }
     }
 
-    pub fn print_json(&self, dataset: DataSet) -> Vec<String> {
+    /// A printer that names and decodes fields with `registry` in place of the IANA registry,
+    /// e.g. one extended with vendor elements by `Registry::load_xml`.
+    pub fn with_registry(registry: Registry) -> IpfixPrinter {
+        IpfixPrinter {
+            registry: Cow::Owned(registry),
+            formatters: IpfixPrinter::get_default_formatters(),
+        }
+    }
 
-        let mut strings = Vec::<String>::new();
+    /// Renders each record of `dataset` as a JSON object. Structured lists become objects with
+    /// their semantic, and an array of the values of a basicList or of the records of a
+    /// subTemplateList, or an array of the groups of a subTemplateMultiList.
+    pub fn print_json(&self, dataset: DataSet) -> Vec<String> {
+        dataset.records.iter().map(|record| self.record_json(record)).collect()
+    }
 
-        for record in dataset.records {
-            let mut output = String::new();
-            output += "{";
+    fn record_json(&self, record: &DataRecord) -> String {
+        let mut output = ObjectWriter::new();
+
+        // repeated IEs are grouped, keeping the order they appear in within each group
+        for (element, indices) in record.element_indices() {
+            if let Some(ie) = self.registry.get(element) {
+                let mut data = indices.into_iter()
+                    .map(|index| self.field_json(record, index, ie.data_type))
+                    .collect::<Vec<_>>();
+                let value = if data.len() == 1 {
+                    data.remove(0)
+                } else {
+                    format!("[{}]", data.join(","))
+                };
+                output.member(&ie.name, &value);
+            }
+        }
+        output.finish()
+    }
 
-            for (x, data) in record.values {
+    // values of the wrong size for their IE are printed as null
+    fn field_json(&self,
+                  record: &DataRecord,
+                  index: usize,
+                  data_type: registry::DataType)
+                  -> String {
+        if let Some(list) = record.field_list(index) {
+            return self.list_json(list);
+        }
+        let (field, ref data) = record.fields()[index];
+        self.format(field.element_id(), data_type, data).unwrap_or_else(|| "null".into())
+    }
 
-                if let Some(&(name, formatter)) = self.formatters.get(&x) {
-                    output += format!(r#""{}":{},"#, name, formatter(data)).as_str();
+    fn list_json(&self, list: &StructuredList) -> String {
+        let records_json = |records: &[DataRecord]| {
+            let records = records.iter().map(|record| self.record_json(record)).collect::<Vec<_>>();
+            format!("[{}]", records.join(","))
+        };
+
+        let mut output = ObjectWriter::new();
+        output.member("semantic", &semantic_json(list.semantic()));
+        match *list {
+            StructuredList::Basic { field, ref values, .. } => {
+                if let Some(element) = self.registry.get(field.element_id()) {
+                    let values = (0..values.fields().len())
+                        .map(|index| self.field_json(values, index, element.data_type))
+                        .collect::<Vec<_>>();
+                    output.member(&element.name, &format!("[{}]", values.join(",")));
                 }
             }
-            output = String::from(&output[0..output.len() - 1]);
-            output += "}";
-            strings.push(output);
+            StructuredList::SubTemplate { template_id, ref records, .. } => {
+                output.member("template_id", &template_id.to_string());
+                output.member("records", &records_json(records));
+            }
+            StructuredList::SubTemplateMulti { ref lists, .. } => {
+                let lists = lists.iter()
+                    .map(|&(template_id, ref records)| {
+                        let mut group = ObjectWriter::new();
+                        group.member("template_id", &template_id.to_string());
+                        group.member("records", &records_json(records));
+                        group.finish()
+                    })
+                    .collect::<Vec<_>>();
+                output.member("lists", &format!("[{}]", lists.join(",")));
+            }
         }
-        strings
+        output.finish()
     }
 
-    fn get_default_parsers() -> HashMap<u16, (&'static str, fn(&[u8]) -> String)> {
-        field_parser!{
-            1 => ("octetDeltaCount", be_int),
-            2 => ("packetDeltaCount", be_int),
-            4 => ("protocolIdentifier", be_int),
-            5 => ("classOfServiceIPv4", be_int),
-            6 => ("tcpControlBits", be_int),
-            7 => ("sourceTransportPort", be_int),
-            8 => ("sourceIPv4Address", ipv4_addr),
-            9 => ("sourceIPv4Mask", be_int),
-            10 => ("ingressInterface", be_int),
-            11 => ("destinationTransportPort", be_int),
-            12 => ("destinationIPv4Address", ipv4_addr),
-            13 => ("destinationIPv4Mask", be_int),
-            14 => ("egressInterface", be_int),
-            15 => ("ipNextHopIPv4Address", ipv4_addr),
-            16 => ("bgpSourceAsNumber", be_int),
-            17 => ("bgpDestinationAsNumber", be_int),
-            18 => ("bgpNextHopIPv4Address", be_int),
-            19 => ("postMCastPacketDeltaCount", be_int),
-            20 => ("postMCastOctetDeltaCount", be_int),
-            21 => ("flowEndSysUpTime", be_int),
-            22 => ("flowStartSysUpTime", be_int),
-            23 => ("postOctetDeltaCount", be_int),
-            24 => ("postPacketDeltaCount", be_int),
-            25 => ("minimumPacketLength", be_int),
-            26 => ("maximumPacketLength", be_int),
-            27 => ("sourceIPv6Address", ipv6_addr),
-            28 => ("destinationIPv6Address", ipv6_addr),
-            29 => ("sourceIPv6Mask", be_int),
-            30 => ("destinationIPv6Mask", be_int),
-            31 => ("flowLabelIPv6", be_int),
-            32 => ("icmpTypeCodeIPv4", be_int),
-            33 => ("igmpType", be_int),
-            36 => ("flowActiveTimeOut", be_int),
-            37 => ("flowInactiveTimeout", be_int),
-            40 => ("exportedOctetTotalCount", be_int),
-            41 => ("exportedMessageTotalCount", be_int),
-            42 => ("exportedFlowTotalCount", be_int),
-            44 => ("sourceIPv4Prefix", be_int),
-            45 => ("destinationIPv4Prefix", be_int),
-            46 => ("mplsTopLabelType", be_int),
-            47 => ("mplsTopLabelIPv4Address", ipv4_addr),
-            52 => ("minimumTtl", be_int),
-            53 => ("maximumTtl", be_int),
-            54 => ("identificationIPv4", be_int),
-            55 => ("postClassOfServiceIPv4", be_int),
-            56 => ("sourceMacAddress", be_int),
-            57 => ("postDestinationMacAddr", be_int),
-            58 => ("vlanId", be_int),
-            59 => ("postVlanId", be_int),
-            60 => ("ipVersion", be_int),
-            62 => ("ipNextHopIPv6Address", ipv6_addr),
-            63 => ("bgpNextHopIPv6Address", ipv6_addr),
-            64 => ("ipv6ExtensionHeaders", be_int),
-            70 => ("mplsTopLabelStackEntry", mpls_stack),
-            71 => ("mplsLabelStackEntry2", mpls_stack),
-            72 => ("mplsLabelStackEntry3", mpls_stack),
-            73 => ("mplsLabelStackEntry4", mpls_stack),
-            74 => ("mplsLabelStackEntry5", mpls_stack),
-            75 => ("mplsLabelStackEntry6", mpls_stack),
-            76 => ("mplsLabelStackEntry7", mpls_stack),
-            77 => ("mplsLabelStackEntry8", mpls_stack),
-            78 => ("mplsLabelStackEntry9", mpls_stack),
-            79 => ("mplsLabelStackEntry10", mpls_stack),
-            80 => ("destinationMacAddress", be_int),
-            81 => ("postSourceMacAddress", be_int),
-            82 => ("interfaceName", be_int),
-            83 => ("interfaceDescription", be_int),
-            84 => ("samplerName", be_int),
-            85 => ("octetTotalCount", be_int),
-            86 => ("packetTotalCount", be_int),
-            88 => ("fragmentOffsetIPv4", be_int),
-            128 => ("bgpNextAdjacentAsNumber", be_int),
-            129 => ("bgpPrevAdjacentAsNumber", be_int),
-            130 => ("exporterIPv4Address", ipv4_addr),
-            131 => ("exporterIPv6Address", ipv6_addr),
-            132 => ("droppedOctetDeltaCount", be_int),
-            133 => ("droppedPacketDeltaCount", be_int),
-            134 => ("droppedOctetTotalCount", be_int),
-            135 => ("droppedPacketTotalCount", be_int),
-            136 => ("flowEndReason", be_int),
-            137 => ("classOfServiceIPv6", be_int),
-            138 => ("postClassOfServiceIPv6", be_int),
-            139 => ("icmpTypeCodeIPv6", be_int),
-            140 => ("mplsTopLabelIPv6Address", ipv6_addr),
-            141 => ("lineCardId", be_int),
-            142 => ("portId", be_int),
-            143 => ("meteringProcessId", be_int),
-            144 => ("exportingProcessId", be_int),
-            145 => ("templateId", be_int),
-            146 => ("wlanChannelId", be_int),
-            147 => ("wlanSsid", be_int),
-            148 => ("flowId", be_int),
-            149 => ("sourceId", be_int),
-            150 => ("flowStartSeconds", be_int),
-            151 => ("flowEndSeconds", be_int),
-            152 => ("flowStartMilliSeconds", be_int),
-            153 => ("flowEndMilliSeconds", be_int),
-            154 => ("flowStartMicroSeconds", be_int),
-            155 => ("flowEndMicroSeconds", be_int),
-            156 => ("flowStartNanoSeconds", be_int),
-            157 => ("flowEndNanoSeconds", be_int),
-            158 => ("flowStartDeltaMicroSeconds", be_int),
-            159 => ("flowEndDeltaMicroSeconds", be_int),
-            160 => ("systemInitTimeMilliSeconds", be_int),
-            161 => ("flowDurationMilliSeconds", be_int),
-            162 => ("flowDurationMicroSeconds", be_int),
-            163 => ("observedFlowTotalCount", be_int),
-            164 => ("ignoredPacketTotalCount", be_int),
-            165 => ("ignoredOctetTotalCount", be_int),
-            166 => ("notSentFlowTotalCount", be_int),
-            167 => ("notSentPacketTotalCount", be_int),
-            168 => ("notSentOctetTotalCount", be_int),
-            169 => ("destinationIPv6Prefix", be_int),
-            170 => ("sourceIPv6Prefix", be_int),
-            171 => ("postOctetTotalCount", be_int),
-            172 => ("postPacketTotalCount", be_int),
-            173 => ("flowKeyIndicator", be_int),
-            174 => ("postMCastPacketTotalCount", be_int),
-            175 => ("postMCastOctetTotalCount", be_int),
-            176 => ("icmpTypeIPv4", be_int),
-            177 => ("icmpCodeIPv4", be_int),
-            178 => ("icmpTypeIPv6", be_int),
-            179 => ("icmpCodeIPv6", be_int),
-            180 => ("udpSourcePort", be_int),
-            181 => ("udpDestinationPort", be_int),
-            182 => ("tcpSourcePort", be_int),
-            183 => ("tcpDestinationPort", be_int),
-            184 => ("tcpSequenceNumber", be_int),
-            185 => ("tcpAcknowledgementNumber", be_int),
-            186 => ("tcpWindowSize", be_int),
-            187 => ("tcpUrgentPointer", be_int),
-            188 => ("tcpHeaderLength", be_int),
-            189 => ("ipHeaderLength", be_int),
-            190 => ("totalLengthIPv4", be_int),
-            191 => ("payloadLengthIPv6", be_int),
-            192 => ("ipTimeToLive", be_int),
-            193 => ("nextHeaderIPv6", be_int),
-            194 => ("ipClassOfService", be_int),
-            195 => ("ipDiffServCodePoint", be_int),
-            196 => ("ipPrecedence", be_int),
-            197 => ("fragmentFlagsIPv4", be_int),
-            198 => ("octetDeltaSumOfSquares", be_int),
-            199 => ("octetTotalSumOfSquares", be_int),
-            200 => ("mplsTopLabelTtl", be_int),
-            201 => ("mplsLabelStackLength", be_int),
-            202 => ("mplsLabelStackDepth", be_int),
-            203 => ("mplsTopLabelExp", be_int),
-            204 => ("ipPayloadLength", be_int),
-            205 => ("udpMessageLength", be_int),
-            206 => ("isMulticast", be_int),
-            207 => ("internetHeaderLengthIPv4", be_int),
-            208 => ("ipv4Options", be_int),
-            209 => ("tcpOptions", be_int),
-            210 => ("paddingOctets", be_int),
-            213 => ("headerLengthIPv4", be_int),
-            214 => ("mplsPayloadLength", be_int)
+    fn format(&self,
+              element: ElementId,
+              data_type: registry::DataType,
+              data: &[u8])
+              -> Option<String> {
+        match self.formatters.get(&element) {
+            Some(formatter) => formatter(data),
+            None => format_value(FieldValue::decode(data_type, data).ok()?),
         }
     }
+
+    fn get_default_formatters() -> HashMap<ElementId, Formatter> {
+        // the MPLS label stack sections, which are octet arrays to IANA
+        (70..80).map(|id| (ElementId::iana(id), mpls_stack as Formatter)).collect()
+    }
 }
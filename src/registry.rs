@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use super::iana_xml::{self, Record};
+use super::ElementId;
+
+/// The abstract data types of RFC 7012 §3.1, which say how an Information Element's value is
+/// encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DataType {
+    OctetArray,
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Unsigned64,
+    Signed8,
+    Signed16,
+    Signed32,
+    Signed64,
+    Float32,
+    Float64,
+    Boolean,
+    MacAddress,
+    String,
+    DateTimeSeconds,
+    DateTimeMilliseconds,
+    DateTimeMicroseconds,
+    DateTimeNanoseconds,
+    Ipv4Address,
+    Ipv6Address,
+    BasicList,
+    SubTemplateList,
+    SubTemplateMultiList,
+}
+
+/// The data type semantics of RFC 7012 §3.2, which say how a value is to be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Semantics {
+    Default,
+    Quantity,
+    TotalCounter,
+    DeltaCounter,
+    Identifier,
+    Flags,
+    List,
+    SnmpCounter,
+    SnmpGauge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    Current,
+    Deprecated,
+}
+
+/// The definition of an Information Element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InformationElement {
+    pub name: String,
+    pub data_type: DataType,
+    pub semantics: Semantics,
+    pub units: Option<String>,
+    pub status: Status,
+}
+
+impl InformationElement {
+    /// A current element with default semantics and no units.
+    pub fn new<S: Into<String>>(name: S, data_type: DataType) -> InformationElement {
+        InformationElement {
+            name: name.into(),
+            data_type,
+            semantics: Semantics::Default,
+            units: None,
+            status: Status::Current,
+        }
+    }
+}
+
+/// Information Element definitions, looked up by enterprise number and element id.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    elements: HashMap<ElementId, InformationElement>,
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Registry {
+        Registry { elements: HashMap::new() }
+    }
+
+    /// A registry of the IANA Information Elements, as of the copy of the IANA registry in
+    /// data/ipfix.xml.
+    pub fn iana() -> Registry {
+        let mut registry = Registry::new();
+        for &(id, name, data_type, semantics, units, status) in IANA_ELEMENTS {
+            registry.insert(ElementId::iana(id),
+                            InformationElement {
+                                name: name.into(),
+                                data_type,
+                                semantics,
+                                units: units.map(Into::into),
+                                status,
+                            });
+        }
+        registry
+    }
+
+    pub fn get<E: Into<ElementId>>(&self, element: E) -> Option<&InformationElement> {
+        self.elements.get(&element.into())
+    }
+
+    /// Add an element, replacing any existing definition of the same id.
+    pub fn insert<E: Into<ElementId>>(&mut self, element: E, definition: InformationElement) {
+        self.elements.insert(element.into(), definition);
+    }
+
+    /// Add the elements defined in an XML file in the format of the IANA registry, replacing
+    /// any existing definitions of the same ids, and return how many were added. Elements of
+    /// other enterprises carry their Private Enterprise Number in an `<enterpriseId>`, as in the
+    /// information model files of libfixbuf and YAF:
+    ///
+    /// ```xml
+    /// <registry id="ipfix-information-elements">
+    ///   <record>
+    ///     <name>ntopL7Proto</name>
+    ///     <dataType>unsigned16</dataType>
+    ///     <enterpriseId>35632</enterpriseId>
+    ///     <elementId>118</elementId>
+    ///   </record>
+    /// </registry>
+    /// ```
+    ///
+    /// Nothing is added unless the whole file is valid.
+    pub fn load_xml<R: Read>(&mut self, reader: R) -> io::Result<usize> {
+        let records = iana_xml::element_records(reader)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let mut elements = Vec::new();
+        for record in &records {
+            if let Some(element) = element(record)? {
+                elements.push(element);
+            }
+        }
+        let count = elements.len();
+        for (element, definition) in elements {
+            self.insert(element, definition);
+        }
+        Ok(count)
+    }
+}
+
+// The element defined by a registry <record>, or None for records that don't define a single
+// element, such as the ranges reserved for NetFlow v9 compatibility.
+fn element(record: &Record) -> io::Result<Option<(ElementId, InformationElement)>> {
+    let field = |name: &str| record.get(name).map(String::as_str).filter(|value| !value.is_empty());
+    let invalid = |what: &str, value: &str| {
+        let name = field("name").unwrap_or("");
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("invalid {} {:?} for element {:?}", what, value, name))
+    };
+
+    let id = match field("elementId").and_then(|id| id.parse::<u16>().ok()) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let enterprise_number = match field("enterpriseId") {
+        Some(pen) => pen.parse::<u32>().map_err(|_| invalid("enterprise number", pen))?,
+        None => 0,
+    };
+    let name = field("name").ok_or_else(|| invalid("name", ""))?;
+    let data_type = field("dataType").unwrap_or("");
+    let data_type = parse_data_type(data_type).ok_or_else(|| invalid("data type", data_type))?;
+    let semantics = field("dataTypeSemantics").unwrap_or("default");
+    let semantics = parse_semantics(semantics)
+        .ok_or_else(|| invalid("data type semantics", semantics))?;
+    let status = field("status").unwrap_or("current");
+    let status = parse_status(status).ok_or_else(|| invalid("status", status))?;
+
+    Ok(Some((ElementId::enterprise(enterprise_number, id),
+             InformationElement {
+                 name: name.into(),
+                 data_type,
+                 semantics,
+                 units: field("units").map(Into::into),
+                 status,
+             })))
+}
+
+/// The IANA registry shared by everything that isn't given one of its own.
+pub(crate) fn default_registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::iana)
+}
+
+// (id, name, abstract data type, semantics, units, status)
+type IanaElement = (u16, &'static str, DataType, Semantics, Option<&'static str>, Status);
+
+// IANA_ELEMENTS, generated by build.rs from data/ipfix.xml, and parse_data_type, parse_semantics
+// and parse_status, generated from the tables build.rs maps the registry's names with
+include!(concat!(env!("OUT_DIR"), "/iana.rs"));
@@ -0,0 +1,90 @@
+// Sequence numbers in IPFIX count the data records an Observation Domain has exported, not
+// messages, so the next expected number is the current one plus the records in the message.
+
+// How far behind the expected sequence number a message can be and still be taken for a
+// duplicate or a late arrival, rather than the exporter having restarted its count.
+const REORDER_WINDOW: u32 = 1 << 16;
+
+/// A discontinuity in the sequence numbers of one Observation Domain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// `missing` data records were lost between the previous message and this one.
+    Gap {
+        expected: u32,
+        received: u32,
+        missing: u32,
+    },
+    /// The message carries records that were already accounted for: a duplicate, or a message
+    /// that arrived after ones sent later than it.
+    Duplicate { expected: u32, received: u32 },
+    /// The sequence number went back to zero or jumped far back, most likely because the
+    /// exporter restarted.
+    Reset { expected: u32, received: u32 },
+}
+
+/// Running totals for one Observation Domain of one session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub messages: u64,
+    pub records: u64,
+    pub lost_records: u64,
+    pub gaps: u64,
+    pub duplicates: u64,
+    pub resets: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct SequenceTracker {
+    // None until the first message, and after any message whose records could not be counted
+    expected: Option<u32>,
+    stats: SequenceStats,
+}
+
+impl SequenceTracker {
+    pub(crate) fn stats(&self) -> SequenceStats {
+        self.stats
+    }
+
+    /// Forget the expected sequence number, so the next message is taken as the new baseline.
+    pub(crate) fn resync(&mut self) {
+        self.expected = None;
+    }
+
+    pub(crate) fn update(&mut self, received: u32, records: u32) -> Option<SequenceEvent> {
+        self.stats.messages += 1;
+        self.stats.records += records as u64;
+
+        let expected = match self.expected {
+            Some(expected) => expected,
+            None => {
+                self.expected = Some(received.wrapping_add(records));
+                return None;
+            }
+        };
+
+        // distance forward from the expected number, modulo 2^32
+        let ahead = received.wrapping_sub(expected);
+        let behind = expected.wrapping_sub(received);
+        let event = if ahead == 0 {
+            None
+        } else if ahead < behind {
+            self.stats.gaps += 1;
+            self.stats.lost_records += ahead as u64;
+            Some(SequenceEvent::Gap {
+                expected,
+                received,
+                missing: ahead,
+            })
+        } else if received != 0 && behind <= REORDER_WINDOW {
+            // a late message doesn't move the expected number back
+            self.stats.duplicates += 1;
+            return Some(SequenceEvent::Duplicate { expected, received });
+        } else {
+            self.stats.resets += 1;
+            Some(SequenceEvent::Reset { expected, received })
+        };
+
+        self.expected = Some(received.wrapping_add(records));
+        event
+    }
+}
@@ -0,0 +1,352 @@
+// serde support, behind the "serde" feature. Records serialize as maps from Information Element
+// names to typed values, the way IpfixPrinter prints them: names and types come from the IANA
+// registry, repeated elements become sequences and values that can't be decoded become none.
+// Elements the registry doesn't know are keyed "<enterprise number>.<element id>" and serialized
+// as bytes. Sets of an options template say how many of their fields are scope fields.
+// Structured lists that were decoded serialize as maps of their semantic and values or
+// records. `with_registry` serializes them with the names and types of another registry.
+
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, SerializeStructVariant, Serializer};
+
+use super::error::ValueError;
+use super::registry::{default_registry, DataType, Registry};
+use super::{DataRecord, DataSet, ElementId, FieldValue, IpfixHeader, ListSemantic,
+            ParsedMessage, SequenceEvent, StructuredList, TemplateKind};
+
+impl Serialize for IpfixHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut header = serializer.serialize_struct("IpfixHeader", 5)?;
+        header.serialize_field("version", &self.version)?;
+        header.serialize_field("length", &self.length)?;
+        header.serialize_field("export_time", &self.export_time)?;
+        header.serialize_field("sequence_number", &self.sequence_number)?;
+        header.serialize_field("observation_domain_id", &self.observation_domain_id)?;
+        header.end()
+    }
+}
+
+/// A message, set, record or structured list that serializes with the names and types of
+/// `registry` in place of the IANA registry. Made by their `with_registry`.
+pub struct WithRegistry<'r, T: 'r> {
+    value: &'r T,
+    registry: &'r Registry,
+}
+
+impl<'a> ParsedMessage<'a> {
+    /// The message, serialized with the names and types of `registry`.
+    pub fn with_registry<'r>(&'r self, registry: &'r Registry) -> WithRegistry<'r, Self> {
+        WithRegistry { value: self, registry }
+    }
+}
+
+impl<'a> DataSet<'a> {
+    /// The set, serialized with the names and types of `registry`.
+    pub fn with_registry<'r>(&'r self, registry: &'r Registry) -> WithRegistry<'r, Self> {
+        WithRegistry { value: self, registry }
+    }
+}
+
+impl<'a> DataRecord<'a> {
+    /// The record, serialized with the names and types of `registry`, e.g. one extended with
+    /// vendor elements by `Registry::load_xml`.
+    pub fn with_registry<'r>(&'r self, registry: &'r Registry) -> WithRegistry<'r, Self> {
+        WithRegistry { value: self, registry }
+    }
+}
+
+impl<'a> StructuredList<'a> {
+    /// The list, serialized with the names and types of `registry`.
+    pub fn with_registry<'r>(&'r self, registry: &'r Registry) -> WithRegistry<'r, Self> {
+        WithRegistry { value: self, registry }
+    }
+}
+
+impl<'a> Serialize for ParsedMessage<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_message(self, default_registry(), serializer)
+    }
+}
+
+impl<'r, 'a> Serialize for WithRegistry<'r, ParsedMessage<'a>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_message(self.value, self.registry, serializer)
+    }
+}
+
+fn serialize_message<S: Serializer>(message: &ParsedMessage,
+                                    registry: &Registry,
+                                    serializer: S)
+                                    -> Result<S::Ok, S::Error> {
+    let sets = message.sets.iter().map(|set| set.with_registry(registry)).collect::<Vec<_>>();
+    let recovered = message.recovered
+        .iter()
+        .map(|(header, set)| (header, set.with_registry(registry)))
+        .collect::<Vec<_>>();
+
+    let length = if message.sys_uptime.is_some() { 5 } else { 4 };
+    let mut map = serializer.serialize_struct("ParsedMessage", length)?;
+    map.serialize_field("header", &message.header)?;
+    map.serialize_field("sets", &sets)?;
+    map.serialize_field("recovered", &recovered)?;
+    map.serialize_field("sequence_event", &message.sequence_event)?;
+    // only NetFlow packets have one
+    if let Some(sys_uptime) = message.sys_uptime {
+        map.serialize_field("sys_uptime", &sys_uptime)?;
+    }
+    map.end()
+}
+
+impl Serialize for SequenceEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (index, variant, expected, received, missing) = match *self {
+            SequenceEvent::Gap { expected, received, missing } => {
+                (0, "Gap", expected, received, Some(missing))
+            }
+            SequenceEvent::Duplicate { expected, received } => {
+                (1, "Duplicate", expected, received, None)
+            }
+            SequenceEvent::Reset { expected, received } => (2, "Reset", expected, received, None),
+        };
+        let length = if missing.is_some() { 3 } else { 2 };
+        let mut event =
+            serializer.serialize_struct_variant("SequenceEvent", index, variant, length)?;
+        event.serialize_field("expected", &expected)?;
+        event.serialize_field("received", &received)?;
+        if let Some(missing) = missing {
+            event.serialize_field("missing", &missing)?;
+        }
+        event.end()
+    }
+}
+
+impl<'a> Serialize for DataSet<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_set(self, default_registry(), serializer)
+    }
+}
+
+impl<'r, 'a> Serialize for WithRegistry<'r, DataSet<'a>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_set(self.value, self.registry, serializer)
+    }
+}
+
+fn serialize_set<S: Serializer>(set: &DataSet,
+                                registry: &Registry,
+                                serializer: S)
+                                -> Result<S::Ok, S::Error> {
+    let scope_field_count = match set.template_kind {
+        TemplateKind::OptionsTemplate { scope_field_count } => Some(scope_field_count),
+        TemplateKind::Template => None,
+    };
+    let length = if scope_field_count.is_some() { 3 } else { 2 };
+    let mut map = serializer.serialize_struct("DataSet", length)?;
+    map.serialize_field("template_id", &set.template_id())?;
+    // only sets of an options template have one
+    if let Some(scope_field_count) = scope_field_count {
+        map.serialize_field("scope_field_count", &scope_field_count)?;
+    }
+    map.serialize_field("records", &with_registry(&set.records, registry))?;
+    map.end()
+}
+
+impl<'a> Serialize for DataRecord<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_record(self, default_registry(), serializer)
+    }
+}
+
+impl<'r, 'a> Serialize for WithRegistry<'r, DataRecord<'a>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_record(self.value, self.registry, serializer)
+    }
+}
+
+// repeated IEs are grouped, in the order IpfixPrinter prints them
+fn serialize_record<S: Serializer>(record: &DataRecord,
+                                   registry: &Registry,
+                                   serializer: S)
+                                   -> Result<S::Ok, S::Error> {
+    let elements = record.element_indices();
+    let mut map = serializer.serialize_map(Some(elements.len()))?;
+    for (element, indices) in elements {
+        let (name, data_type) = describe(registry, element);
+        let mut values = indices.into_iter()
+            .map(|index| field_value(record, index, data_type, registry));
+        if values.len() == 1 {
+            map.serialize_entry(&name, &values.next().unwrap())?;
+        } else {
+            map.serialize_entry(&name, &values.collect::<Vec<_>>())?;
+        }
+    }
+    map.end()
+}
+
+// records that serialize with the names and types of `registry`
+fn with_registry<'r, 'a>(records: &'r [DataRecord<'a>],
+                         registry: &'r Registry)
+                         -> Vec<WithRegistry<'r, DataRecord<'a>>> {
+    records.iter().map(|record| record.with_registry(registry)).collect()
+}
+
+// the key and type of an element, "<pen>.<id>" and octetArray when the registry doesn't know it
+fn describe(registry: &Registry, element: ElementId) -> (Cow<'_, str>, DataType) {
+    match registry.get(element) {
+        Some(ie) => (Cow::Borrowed(ie.name.as_str()), ie.data_type),
+        None => {
+            let name = format!("{}.{}", element.enterprise_number, element.id);
+            (Cow::Owned(name), DataType::OctetArray)
+        }
+    }
+}
+
+// a decoded value, or none when it couldn't be decoded
+enum Value<'r, 'a: 'r> {
+    Field(Result<FieldValue<'r>, ValueError>),
+    List(WithRegistry<'r, StructuredList<'a>>),
+}
+
+// the value of the field at `index`, its decoded list if it has one
+fn field_value<'r, 'a>(record: &'r DataRecord<'a>,
+                       index: usize,
+                       data_type: DataType,
+                       registry: &'r Registry)
+                       -> Value<'r, 'a> {
+    match record.field_list(index) {
+        Some(list) => Value::List(list.with_registry(registry)),
+        None => Value::Field(FieldValue::decode(data_type, &record.fields[index].1)),
+    }
+}
+
+impl<'r, 'a> Serialize for Value<'r, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Value::Field(Ok(ref value)) => value.serialize(serializer),
+            Value::Field(Err(_)) => serializer.serialize_none(),
+            Value::List(ref list) => list.serialize(serializer),
+        }
+    }
+}
+
+impl<'a> Serialize for StructuredList<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_list(self, default_registry(), serializer)
+    }
+}
+
+impl<'r, 'a> Serialize for WithRegistry<'r, StructuredList<'a>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_list(self.value, self.registry, serializer)
+    }
+}
+
+fn serialize_list<S: Serializer>(list: &StructuredList,
+                                 registry: &Registry,
+                                 serializer: S)
+                                 -> Result<S::Ok, S::Error> {
+    let length = match *list {
+        StructuredList::SubTemplate { .. } => 3,
+        _ => 2,
+    };
+    let mut map = serializer.serialize_map(Some(length))?;
+    map.serialize_entry("semantic", &list.semantic())?;
+    match *list {
+        StructuredList::Basic { field, ref values, .. } => {
+            // the values are serialized like a record's, with the name of their element
+            let (name, data_type) = describe(registry, field.element_id());
+            let values = (0..values.fields.len())
+                .map(|index| field_value(values, index, data_type, registry))
+                .collect::<Vec<_>>();
+            map.serialize_entry(&name, &values)?;
+        }
+        StructuredList::SubTemplate { template_id, ref records, .. } => {
+            map.serialize_entry("template_id", &template_id)?;
+            map.serialize_entry("records", &with_registry(records, registry))?;
+        }
+        StructuredList::SubTemplateMulti { ref lists, .. } => {
+            let lists = lists.iter()
+                .map(|&(template_id, ref records)| {
+                    RecordGroup(template_id, with_registry(records, registry))
+                })
+                .collect::<Vec<_>>();
+            map.serialize_entry("lists", &lists)?;
+        }
+    }
+    map.end()
+}
+
+// one template's records in a subTemplateMultiList
+struct RecordGroup<'r, 'a: 'r>(u16, Vec<WithRegistry<'r, DataRecord<'a>>>);
+
+impl<'r, 'a> Serialize for RecordGroup<'r, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut group = serializer.serialize_struct("RecordGroup", 2)?;
+        group.serialize_field("template_id", &self.0)?;
+        group.serialize_field("records", &self.1)?;
+        group.end()
+    }
+}
+
+// named semantics serialize as their name, unassigned ones as their number
+impl Serialize for ListSemantic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ListSemantic::Unassigned(value) => serializer.serialize_u8(value),
+            semantic => serializer.serialize_str(semantic.name().unwrap_or_default()),
+        }
+    }
+}
+
+// Addresses serialize the way serde serializes std's: as text for human-readable formats, as
+// octets otherwise. Times are a number of their own unit since the UNIX epoch, negative for
+// times before it.
+impl<'a> Serialize for FieldValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            FieldValue::Unsigned8(value) => serializer.serialize_u8(value),
+            FieldValue::Unsigned16(value) => serializer.serialize_u16(value),
+            FieldValue::Unsigned32(value) => serializer.serialize_u32(value),
+            FieldValue::Unsigned64(value) => serializer.serialize_u64(value),
+            FieldValue::Signed8(value) => serializer.serialize_i8(value),
+            FieldValue::Signed16(value) => serializer.serialize_i16(value),
+            FieldValue::Signed32(value) => serializer.serialize_i32(value),
+            FieldValue::Signed64(value) => serializer.serialize_i64(value),
+            FieldValue::Float32(value) => serializer.serialize_f32(value),
+            FieldValue::Float64(value) => serializer.serialize_f64(value),
+            FieldValue::Boolean(value) => serializer.serialize_bool(value),
+            FieldValue::MacAddress(s) => {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(&format_args!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                                                         s[0], s[1], s[2], s[3], s[4], s[5]))
+                } else {
+                    s.serialize(serializer)
+                }
+            }
+            FieldValue::OctetArray(value) => serializer.serialize_bytes(value),
+            FieldValue::String(ref value) => serializer.serialize_str(value),
+            FieldValue::DateTimeSeconds(time) => serialize_time(serializer, time, 1),
+            FieldValue::DateTimeMilliseconds(time) => serialize_time(serializer, time, 1_000),
+            FieldValue::DateTimeMicroseconds(time) => serialize_time(serializer, time, 1_000_000),
+            FieldValue::DateTimeNanoseconds(time) => {
+                serialize_time(serializer, time, 1_000_000_000)
+            }
+            FieldValue::Ipv4Address(address) => address.serialize(serializer),
+            FieldValue::Ipv6Address(address) => address.serialize(serializer),
+        }
+    }
+}
+
+#[inline]
+fn serialize_time<S: Serializer>(serializer: S,
+                                 time: SystemTime,
+                                 per_second: u128)
+                                 -> Result<S::Ok, S::Error> {
+    let units = |nanoseconds: u128| (nanoseconds * per_second / 1_000_000_000) as u64;
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => serializer.serialize_u64(units(since.as_nanos())),
+        Err(before) => serializer.serialize_i64(-(units(before.duration().as_nanos()) as i64)),
+    }
+}
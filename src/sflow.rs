@@ -0,0 +1,472 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::conversions::*;
+use super::error::SflowError;
+use super::sequence::{SequenceEvent, SequenceStats, SequenceTracker};
+use super::{DataRecord, DataSet, FieldSpecifier, SetHeader, TemplateKind};
+
+// sFlow v5 (https://sflow.org/sflow_version_5.txt) exports samples rather than flows: a datagram
+// carries copies of the headers of 1 in N packets, and interface counters polled at intervals.
+// Every structure is XDR, big-endian and padded to 4 bytes, and most of them carry their own
+// length, so structures this decoder doesn't know are skipped.
+//
+// Flow samples are decoded into data records by taking apart the sampled packet header and
+// mapping what it holds onto the Information Elements with the same meaning. Counter samples have
+// no equivalent in IPFIX data records, and are returned as they are.
+
+const SFLOW_VERSION: u32 = 5;
+
+const ADDRESS_IPV4: u32 = 1;
+const ADDRESS_IPV6: u32 = 2;
+
+// sample and record formats of the standard enterprise (0)
+const FLOW_SAMPLE: u32 = 1;
+const COUNTER_SAMPLE: u32 = 2;
+const EXPANDED_FLOW_SAMPLE: u32 = 3;
+const EXPANDED_COUNTER_SAMPLE: u32 = 4;
+const RAW_PACKET_HEADER: u32 = 1;
+const GENERIC_INTERFACE_COUNTERS: u32 = 1;
+
+// header_protocol of a sampled packet header
+const HEADER_ETHERNET: u32 = 1;
+const HEADER_IPV4: u32 = 11;
+const HEADER_IPV6: u32 = 12;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PROTOCOL_ICMPV6: u8 = 58;
+
+// Information Elements the fields of a sample are mapped onto
+const PROTOCOL_IDENTIFIER: u16 = 4;
+const IP_CLASS_OF_SERVICE: u16 = 5;
+const TCP_CONTROL_BITS: u16 = 6;
+const SOURCE_TRANSPORT_PORT: u16 = 7;
+const SOURCE_IPV4_ADDRESS: u16 = 8;
+const INGRESS_INTERFACE: u16 = 10;
+const DESTINATION_TRANSPORT_PORT: u16 = 11;
+const DESTINATION_IPV4_ADDRESS: u16 = 12;
+const EGRESS_INTERFACE: u16 = 14;
+const SOURCE_IPV6_ADDRESS: u16 = 27;
+const DESTINATION_IPV6_ADDRESS: u16 = 28;
+const FLOW_LABEL_IPV6: u16 = 31;
+const ICMP_TYPE_CODE_IPV4: u16 = 32;
+const SAMPLING_INTERVAL: u16 = 34;
+const SOURCE_MAC_ADDRESS: u16 = 56;
+const VLAN_ID: u16 = 58;
+const DESTINATION_MAC_ADDRESS: u16 = 80;
+const ICMP_TYPE_CODE_IPV6: u16 = 139;
+const IP_TTL: u16 = 192;
+const IP_TOTAL_LENGTH: u16 = 224;
+const ETHERNET_TYPE: u16 = 256;
+const DATA_LINK_FRAME_SIZE: u16 = 312;
+
+/// The id the data set of a datagram gets as its template id, as sFlow has no templates.
+pub const SFLOW_TEMPLATE_ID: u16 = 0;
+
+/// Decodes sFlow v5 datagrams, keeping track of the sequence numbers of every agent it has heard
+/// from.
+///
+/// Sampled packet headers come out as data records, with the sampling rate as
+/// `samplingInterval`, so they print and serialize like IPFIX and NetFlow records. Their
+/// counters have to be scaled by the sampling rate to estimate the traffic they stand for.
+///
+/// ```
+/// use ipfix::SflowDecoder;
+///
+/// let mut decoder = SflowDecoder::new();
+/// // no samples from agent 192.0.2.1
+/// let datagram = decoder.parse_datagram(&[0, 0, 0, 5, 0, 0, 0, 1, 192, 0, 2, 1, 0, 0, 0, 0, 0,
+///                                         0, 0, 1, 0, 0, 0x27, 0x10, 0, 0, 0, 0])
+///     .unwrap();
+/// assert_eq!(datagram.agent_address, "192.0.2.1".parse::<std::net::IpAddr>().unwrap());
+/// assert!(datagram.flows.records().is_empty());
+/// ```
+#[derive(Default)]
+pub struct SflowDecoder {
+    sequences: HashMap<(IpAddr, u32), SequenceTracker>,
+}
+
+/// A decoded sFlow datagram.
+pub struct SflowDatagram {
+    pub agent_address: IpAddr,
+    pub sub_agent_id: u32,
+    pub sequence_number: u32,
+    /// Milliseconds since the agent booted.
+    pub uptime: u32,
+    /// Set when datagrams from this agent were lost or repeated since the previous one.
+    pub sequence_event: Option<SequenceEvent>,
+    /// One record per flow sample, with template id `SFLOW_TEMPLATE_ID`.
+    pub flows: DataSet<'static>,
+    pub counters: Vec<CounterSample>,
+}
+
+/// Counters an agent polled from one of its data sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CounterSample {
+    pub sequence_number: u32,
+    /// 0 for an ifIndex, 1 for a smonVlanDataSource, 2 for an entPhysicalEntry.
+    pub source_id_type: u32,
+    pub source_id_index: u32,
+    /// Generic interface counters, if the sample has them.
+    pub interface: Option<InterfaceCounters>,
+}
+
+/// Generic interface counters, as in the IF-MIB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub if_index: u32,
+    pub if_type: u32,
+    pub if_speed: u64,
+    /// 0 unknown, 1 full duplex, 2 half duplex, 3 in, 4 out.
+    pub if_direction: u32,
+    /// Bit 0 is ifAdminStatus up, bit 1 ifOperStatus up.
+    pub if_status: u32,
+    pub if_in_octets: u64,
+    pub if_in_ucast_pkts: u32,
+    pub if_in_multicast_pkts: u32,
+    pub if_in_broadcast_pkts: u32,
+    pub if_in_discards: u32,
+    pub if_in_errors: u32,
+    pub if_in_unknown_protos: u32,
+    pub if_out_octets: u64,
+    pub if_out_ucast_pkts: u32,
+    pub if_out_multicast_pkts: u32,
+    pub if_out_broadcast_pkts: u32,
+    pub if_out_discards: u32,
+    pub if_out_errors: u32,
+    pub if_promiscuous_mode: u32,
+}
+
+impl SflowDecoder {
+    pub fn new() -> SflowDecoder {
+        SflowDecoder::default()
+    }
+
+    /// Sequence number accounting for one sub-agent of `agent_address`, or `None` if no
+    /// datagram has been decoded for it. sFlow numbers datagrams, so the record counts are
+    /// datagram counts.
+    pub fn sequence_stats(&self,
+                          agent_address: IpAddr,
+                          sub_agent_id: u32)
+                          -> Option<SequenceStats> {
+        self.sequences.get(&(agent_address, sub_agent_id)).map(SequenceTracker::stats)
+    }
+
+    pub fn parse_datagram(&mut self, data: &[u8]) -> Result<SflowDatagram, SflowError> {
+        let mut reader = Reader::new(data, 0);
+        let version = reader.u32()?;
+        if version != SFLOW_VERSION {
+            return Err(SflowError::BadVersion(version));
+        }
+        let agent_address = match reader.u32()? {
+            ADDRESS_IPV4 => IpAddr::V4(Ipv4Addr::from(reader.u32()?)),
+            ADDRESS_IPV6 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(reader.bytes(16)?);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            address_type => return Err(SflowError::UnknownAddressType(address_type)),
+        };
+        let sub_agent_id = reader.u32()?;
+        let sequence_number = reader.u32()?;
+        let uptime = reader.u32()?;
+
+        let mut records = Vec::new();
+        let mut counters = Vec::new();
+        for _ in 0..reader.u32()? {
+            let format = reader.u32()?;
+            let mut sample = reader.opaque()?;
+            match format {
+                FLOW_SAMPLE | EXPANDED_FLOW_SAMPLE => {
+                    records.push(parse_flow_sample(&mut sample,
+                                                   format == EXPANDED_FLOW_SAMPLE)?)
+                }
+                COUNTER_SAMPLE | EXPANDED_COUNTER_SAMPLE => {
+                    counters.push(parse_counter_sample(&mut sample,
+                                                       format == EXPANDED_COUNTER_SAMPLE)?)
+                }
+                _ => {}
+            }
+        }
+
+        let sequence_event = self.sequences
+            .entry((agent_address, sub_agent_id))
+            .or_default()
+            .update(sequence_number, 1);
+
+        Ok(SflowDatagram {
+            agent_address,
+            sub_agent_id,
+            sequence_number,
+            uptime,
+            sequence_event,
+            flows: DataSet {
+                header: SetHeader {
+                    set_id: SFLOW_TEMPLATE_ID,
+                    length: 0,
+                },
+                records,
+                template_kind: TemplateKind::Template,
+            },
+            counters,
+        })
+    }
+}
+
+// Reads XDR from `data`, which starts `offset` bytes into the datagram.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Reader<'a> {
+        Reader { data, offset }
+    }
+
+    // `length` bytes, and the padding after them
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], SflowError> {
+        let padded = length.checked_add(3).map(|length| length & !3);
+        match padded {
+            Some(padded) if padded <= self.data.len() => {
+                let bytes = &self.data[..length];
+                self.data = &self.data[padded..];
+                self.offset += padded;
+                Ok(bytes)
+            }
+            _ => Err(SflowError::Truncated { offset: self.offset }),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SflowError> {
+        self.bytes(4).map(|bytes| be_buf_to_u32(bytes).unwrap_or(0))
+    }
+
+    fn u64(&mut self) -> Result<u64, SflowError> {
+        self.bytes(8).map(|bytes| be_buf_to_u64(bytes).unwrap_or(0))
+    }
+
+    // a structure prefixed with its length
+    fn opaque(&mut self) -> Result<Reader<'a>, SflowError> {
+        let length = self.u32()? as usize;
+        let offset = self.offset;
+        self.bytes(length).map(|data| Reader::new(data, offset))
+    }
+}
+
+fn parse_flow_sample(sample: &mut Reader,
+                     expanded: bool)
+                     -> Result<DataRecord<'static>, SflowError> {
+    let mut fields = Vec::new();
+    let _sequence_number = sample.u32()?;
+    // the source id, in one word or in two when expanded
+    sample.u32()?;
+    if expanded {
+        sample.u32()?;
+    }
+    let sampling_rate = sample.u32()?;
+    let _sample_pool = sample.u32()?;
+    let _drops = sample.u32()?;
+    push(&mut fields, SAMPLING_INTERVAL, &sampling_rate.to_be_bytes());
+
+    // interfaces are ifIndex values when their format is 0; other formats are for packets that
+    // were discarded or sent out of several interfaces
+    let (input, output) = if expanded {
+        let input = (sample.u32()?, sample.u32()?);
+        (input, (sample.u32()?, sample.u32()?))
+    } else {
+        let input = sample.u32()?;
+        let output = sample.u32()?;
+        ((input >> 30, input & 0x3FFF_FFFF), (output >> 30, output & 0x3FFF_FFFF))
+    };
+    for &(ident, (format, index)) in [(INGRESS_INTERFACE, input), (EGRESS_INTERFACE, output)]
+        .iter() {
+        if format == 0 && index != 0 {
+            push(&mut fields, ident, &index.to_be_bytes());
+        }
+    }
+
+    for _ in 0..sample.u32()? {
+        let format = sample.u32()?;
+        let mut record = sample.opaque()?;
+        if format != RAW_PACKET_HEADER {
+            continue;
+        }
+        let header_protocol = record.u32()?;
+        let frame_length = record.u32()?;
+        let _stripped = record.u32()?;
+        let header_length = record.u32()? as usize;
+        let header = record.bytes(header_length)?;
+
+        if frame_length <= u16::MAX as u32 {
+            push(&mut fields, DATA_LINK_FRAME_SIZE, &(frame_length as u16).to_be_bytes());
+        }
+        match header_protocol {
+            HEADER_ETHERNET => parse_ethernet(&mut fields, header),
+            HEADER_IPV4 => parse_ipv4(&mut fields, header),
+            HEADER_IPV6 => parse_ipv6(&mut fields, header),
+            _ => {}
+        }
+    }
+    Ok(DataRecord {
+        fields,
+        lists: Vec::new(),
+    })
+}
+
+fn parse_counter_sample(sample: &mut Reader,
+                        expanded: bool)
+                        -> Result<CounterSample, SflowError> {
+    let sequence_number = sample.u32()?;
+    let (source_id_type, source_id_index) = if expanded {
+        (sample.u32()?, sample.u32()?)
+    } else {
+        let source_id = sample.u32()?;
+        (source_id >> 24, source_id & 0x00FF_FFFF)
+    };
+
+    let mut interface = None;
+    for _ in 0..sample.u32()? {
+        let format = sample.u32()?;
+        let mut record = sample.opaque()?;
+        if format == GENERIC_INTERFACE_COUNTERS {
+            interface = Some(parse_interface_counters(&mut record)?);
+        }
+    }
+    Ok(CounterSample {
+        sequence_number,
+        source_id_type,
+        source_id_index,
+        interface,
+    })
+}
+
+fn parse_interface_counters(record: &mut Reader) -> Result<InterfaceCounters, SflowError> {
+    Ok(InterfaceCounters {
+        if_index: record.u32()?,
+        if_type: record.u32()?,
+        if_speed: record.u64()?,
+        if_direction: record.u32()?,
+        if_status: record.u32()?,
+        if_in_octets: record.u64()?,
+        if_in_ucast_pkts: record.u32()?,
+        if_in_multicast_pkts: record.u32()?,
+        if_in_broadcast_pkts: record.u32()?,
+        if_in_discards: record.u32()?,
+        if_in_errors: record.u32()?,
+        if_in_unknown_protos: record.u32()?,
+        if_out_octets: record.u64()?,
+        if_out_ucast_pkts: record.u32()?,
+        if_out_multicast_pkts: record.u32()?,
+        if_out_broadcast_pkts: record.u32()?,
+        if_out_discards: record.u32()?,
+        if_out_errors: record.u32()?,
+        if_promiscuous_mode: record.u32()?,
+    })
+}
+
+// The sampled header is cut off after a configured number of bytes, so each layer is decoded as
+// far as it goes and the ones after it are left out.
+
+fn parse_ethernet(fields: &mut Fields, header: &[u8]) {
+    if header.len() < 14 {
+        return;
+    }
+    push(fields, DESTINATION_MAC_ADDRESS, &header[0..6]);
+    push(fields, SOURCE_MAC_ADDRESS, &header[6..12]);
+    let mut ethertype = be_buf_to_u16(&header[12..14]).unwrap_or(0);
+    let mut payload = &header[14..];
+    if ethertype == ETHERTYPE_VLAN {
+        if payload.len() < 4 {
+            return;
+        }
+        let tci = be_buf_to_u16(&payload[0..2]).unwrap_or(0);
+        push(fields, VLAN_ID, &(tci & 0x0FFF).to_be_bytes());
+        ethertype = be_buf_to_u16(&payload[2..4]).unwrap_or(0);
+        payload = &payload[4..];
+    }
+    push(fields, ETHERNET_TYPE, &ethertype.to_be_bytes());
+    match ethertype {
+        ETHERTYPE_IPV4 => parse_ipv4(fields, payload),
+        ETHERTYPE_IPV6 => parse_ipv6(fields, payload),
+        _ => {}
+    }
+}
+
+fn parse_ipv4(fields: &mut Fields, header: &[u8]) {
+    if header.len() < 20 || header[0] >> 4 != 4 {
+        return;
+    }
+    let header_length = (header[0] & 0x0F) as usize * 4;
+    let protocol = header[9];
+    push(fields, IP_CLASS_OF_SERVICE, &header[1..2]);
+    push(fields, IP_TOTAL_LENGTH, &header[2..4]);
+    push(fields, IP_TTL, &header[8..9]);
+    push(fields, PROTOCOL_IDENTIFIER, &header[9..10]);
+    push(fields, SOURCE_IPV4_ADDRESS, &header[12..16]);
+    push(fields, DESTINATION_IPV4_ADDRESS, &header[16..20]);
+
+    // only the first fragment has the transport header
+    let fragment_offset = be_buf_to_u16(&header[6..8]).unwrap_or(0) & 0x1FFF;
+    if fragment_offset == 0 && header_length >= 20 && header.len() >= header_length {
+        parse_transport(fields, protocol, &header[header_length..]);
+    }
+}
+
+fn parse_ipv6(fields: &mut Fields, header: &[u8]) {
+    if header.len() < 40 || header[0] >> 4 != 6 {
+        return;
+    }
+    let first_word = be_buf_to_u32(&header[0..4]).unwrap_or(0);
+    let payload_length = be_buf_to_u16(&header[4..6]).unwrap_or(0) as u32;
+    let next_header = header[6];
+    push(fields, IP_CLASS_OF_SERVICE, &[(first_word >> 20) as u8]);
+    push(fields, FLOW_LABEL_IPV6, &(first_word & 0x000F_FFFF).to_be_bytes());
+    push(fields, IP_TOTAL_LENGTH, &(payload_length + 40).to_be_bytes());
+    push(fields, PROTOCOL_IDENTIFIER, &header[6..7]);
+    push(fields, IP_TTL, &header[7..8]);
+    push(fields, SOURCE_IPV6_ADDRESS, &header[8..24]);
+    push(fields, DESTINATION_IPV6_ADDRESS, &header[24..40]);
+
+    // extension headers aren't followed, so the transport header is only found right after the
+    // fixed header
+    parse_transport(fields, next_header, &header[40..]);
+}
+
+fn parse_transport(fields: &mut Fields, protocol: u8, header: &[u8]) {
+    match protocol {
+        PROTOCOL_TCP | PROTOCOL_UDP if header.len() >= 4 => {
+            push(fields, SOURCE_TRANSPORT_PORT, &header[0..2]);
+            push(fields, DESTINATION_TRANSPORT_PORT, &header[2..4]);
+            if protocol == PROTOCOL_TCP && header.len() >= 14 {
+                push(fields, TCP_CONTROL_BITS, &header[13..14]);
+            }
+        }
+        PROTOCOL_ICMP if header.len() >= 2 => {
+            push(fields, ICMP_TYPE_CODE_IPV4, &header[0..2]);
+        }
+        PROTOCOL_ICMPV6 if header.len() >= 2 => {
+            push(fields, ICMP_TYPE_CODE_IPV6, &header[0..2]);
+        }
+        _ => {}
+    }
+}
+
+type Fields = Vec<(FieldSpecifier, Cow<'static, [u8]>)>;
+
+fn push(fields: &mut Fields, ident: u16, value: &[u8]) {
+    let field = FieldSpecifier {
+        ident,
+        field_length: value.len() as u16,
+        enterprise_number: None,
+    };
+    fields.push((field, Cow::Owned(value.to_vec())));
+}
+
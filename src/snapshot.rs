@@ -0,0 +1,235 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Instant;
+
+use super::conversions::*;
+use super::exporter::write_field_specifiers;
+use super::{parse_field_specifiers, FieldSpecifier, IpfixConsumer, OptionsTemplate,
+            OptionsTemplateHeader, SessionKey, Template, TemplateHeader, TemplateKey};
+
+// On-disk format of a template cache snapshot, all integers big-endian:
+//
+//   magic "IPFT", format version (u16), record count (u32), then for each record:
+//
+//   kind (u8): 0 template, 1 options template
+//   session (u8 tag, then the value):
+//       0 IPv4 exporter: address (4), port (u16)
+//       1 IPv6 exporter: address (16), port (u16)
+//       2 opaque: id (u64)
+//   observation domain id (u32)
+//   template id (u16), field count (u16), scope field count (u16, options templates only)
+//   field specifiers, as they appear in a template set
+//
+// Bump SNAPSHOT_VERSION whenever this changes.
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"IPFT";
+const SNAPSHOT_VERSION: u16 = 1;
+
+const KIND_TEMPLATE: u8 = 0;
+const KIND_OPTIONS_TEMPLATE: u8 = 1;
+
+const SESSION_IPV4: u8 = 0;
+const SESSION_IPV6: u8 = 1;
+const SESSION_OPAQUE: u8 = 2;
+
+impl IpfixConsumer {
+    /// Write every template and options template, for every session and Observation Domain,
+    /// to `writer`. Load them back with `import_templates` to decode data right after a
+    /// restart instead of waiting for exporters to resend their templates.
+    pub fn export_templates<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut out = Vec::<u8>::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+        let count = (self.templates.len() + self.options_templates.len()) as u32;
+        out.extend_from_slice(&count.to_be_bytes());
+
+        for (&(session, domain_id, id), template) in &self.templates {
+            out.push(KIND_TEMPLATE);
+            write_session(&mut out, session);
+            out.extend_from_slice(&domain_id.to_be_bytes());
+            out.extend_from_slice(&id.to_be_bytes());
+            out.extend_from_slice(&template.header.field_count.to_be_bytes());
+            write_field_specifiers(&mut out, &template.field_specifiers)
+                .map_err(unencodable)?;
+        }
+        for (&(session, domain_id, id), template) in &self.options_templates {
+            out.push(KIND_OPTIONS_TEMPLATE);
+            write_session(&mut out, session);
+            out.extend_from_slice(&domain_id.to_be_bytes());
+            out.extend_from_slice(&id.to_be_bytes());
+            out.extend_from_slice(&template.header.field_count.to_be_bytes());
+            out.extend_from_slice(&template.header.scope_field_count.to_be_bytes());
+            write_field_specifiers(&mut out, &template.field_specifiers)
+                .map_err(unencodable)?;
+        }
+
+        writer.write_all(&out)
+    }
+
+    /// Load templates written by `export_templates`, replacing any the consumer already has
+    /// under the same session, domain and id. Returns the number of templates loaded.
+    pub fn import_templates<R: Read>(&mut self, mut reader: R) -> io::Result<usize> {
+        let mut data = Vec::<u8>::new();
+        reader.read_to_end(&mut data)?;
+        let mut input = Input { data: &data };
+
+        if input.take(4)? != SNAPSHOT_MAGIC {
+            return Err(invalid("not a template snapshot"));
+        }
+        let version = input.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(&format!("unsupported template snapshot version {}", version)));
+        }
+
+        // decode everything before touching the consumer, so that a bad snapshot leaves it as
+        // it was
+        let count = input.u32()?;
+        let received = Instant::now();
+        let mut imported = Vec::<(TemplateKey, Imported)>::new();
+        for _ in 0..count {
+            let kind = input.u8()?;
+            let session = read_session(&mut input)?;
+            let domain_id = input.u32()?;
+            let id = input.u16()?;
+            let field_count = input.u16()?;
+            let template = match kind {
+                KIND_TEMPLATE => {
+                    let field_specifiers = read_field_specifiers(&mut input, field_count)?;
+                    Imported::Template(Template {
+                        header: TemplateHeader {
+                            template_id: id,
+                            field_count,
+                        },
+                        field_specifiers,
+                        received,
+                    })
+                }
+                KIND_OPTIONS_TEMPLATE => {
+                    let scope_field_count = input.u16()?;
+                    let field_specifiers = read_field_specifiers(&mut input, field_count)?;
+                    Imported::OptionsTemplate(OptionsTemplate {
+                        header: OptionsTemplateHeader {
+                            id,
+                            field_count,
+                            scope_field_count,
+                        },
+                        field_specifiers,
+                        received,
+                    })
+                }
+                _ => return Err(invalid("unknown template kind")),
+            };
+            imported.push(((session, domain_id, id), template));
+        }
+
+        if !input.data.is_empty() {
+            return Err(invalid("trailing data after the last template"));
+        }
+
+        for (key, template) in imported {
+            match template {
+                Imported::Template(template) => {
+                    self.options_templates.remove(&key);
+                    self.templates.insert(key, template);
+                }
+                Imported::OptionsTemplate(template) => {
+                    self.templates.remove(&key);
+                    self.options_templates.insert(key, template);
+                }
+            }
+        }
+        Ok(count as usize)
+    }
+}
+
+// a decoded snapshot record, not yet added to the consumer
+enum Imported {
+    Template(Template),
+    OptionsTemplate(OptionsTemplate),
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unencodable(field: FieldSpecifier) -> io::Error {
+    invalid(&format!("element id {} can't be encoded in a template", field.ident))
+}
+
+fn write_session(out: &mut Vec<u8>, session: SessionKey) {
+    match session {
+        SessionKey::Exporter(SocketAddr::V4(addr)) => {
+            out.push(SESSION_IPV4);
+            out.extend_from_slice(&addr.ip().octets());
+            out.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        SessionKey::Exporter(SocketAddr::V6(addr)) => {
+            out.push(SESSION_IPV6);
+            out.extend_from_slice(&addr.ip().octets());
+            out.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        SessionKey::Opaque(id) => {
+            out.push(SESSION_OPAQUE);
+            out.extend_from_slice(&id.to_be_bytes());
+        }
+    }
+}
+
+fn read_session(input: &mut Input) -> io::Result<SessionKey> {
+    match input.u8()? {
+        SESSION_IPV4 => {
+            let ip = input.take(4)?;
+            let ip = Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]);
+            Ok(SessionKey::Exporter(SocketAddr::new(IpAddr::V4(ip), input.u16()?)))
+        }
+        SESSION_IPV6 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(input.take(16)?);
+            let ip = Ipv6Addr::from(octets);
+            Ok(SessionKey::Exporter(SocketAddr::new(IpAddr::V6(ip), input.u16()?)))
+        }
+        SESSION_OPAQUE => {
+            let id = input.take(8).map(be_buf_to_u64)?;
+            Ok(SessionKey::Opaque(id.ok_or_else(|| invalid("truncated template snapshot"))?))
+        }
+        _ => Err(invalid("unknown session type")),
+    }
+}
+
+fn read_field_specifiers(input: &mut Input, count: u16) -> io::Result<Vec<FieldSpecifier>> {
+    match parse_field_specifiers(input.data, count) {
+        Ok((rest, fields)) => {
+            input.data = rest;
+            Ok(fields)
+        }
+        Err(_) => Err(invalid("truncated template snapshot")),
+    }
+}
+
+// the unread part of a snapshot
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid("truncated template snapshot"));
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take(2).map(|bytes| be_buf_to_u16(bytes).unwrap_or(0))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.take(4).map(|bytes| be_buf_to_u32(bytes).unwrap_or(0))
+    }
+}
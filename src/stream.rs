@@ -0,0 +1,146 @@
+use super::conversions::be_buf_to_u16;
+use super::{get_message_length, IpfixConsumer, IpfixError, ParsedMessage, SessionKey,
+            IPFIX_HEADER_LENGTH, IPFIX_VERSION};
+
+const MAX_MESSAGE_LENGTH: usize = 65535;
+
+/// Splits the byte stream of a TCP connection into IPFIX messages, which arrive in chunks of
+/// any size: a chunk can hold part of a message, or several.
+///
+/// Feed it whatever the socket reads with `push`, then take the complete messages out with
+/// `next_message`, which decodes them with an `IpfixConsumer`, or `next_frame` for the raw
+/// bytes. A message header with a wrong version or an impossible length means the stream can't
+/// be split any further: the decoder returns the error once and drops everything it has. Once
+/// `is_failed`, the connection should be closed, along with its session in the consumer.
+///
+/// ```
+/// use ipfix::{FieldSpecifier, IpfixConsumer, IpfixExporter, StreamDecoder};
+///
+/// let mut exporter = IpfixExporter::new();
+/// exporter.add_template(&[FieldSpecifier::new(8, 4)]).unwrap();
+/// let mut message = exporter.message(0);
+/// message.templates();
+/// let bytes = message.finish().unwrap();
+///
+/// let mut consumer = IpfixConsumer::new();
+/// let mut decoder = StreamDecoder::new(1u64);
+/// decoder.push(&bytes[..10]);
+/// assert!(decoder.next_message(&mut consumer).is_none());
+/// decoder.push(&bytes[10..]);
+/// assert!(decoder.next_message(&mut consumer).unwrap().is_ok());
+/// ```
+pub struct StreamDecoder {
+    session: SessionKey,
+    buffer: Vec<u8>,
+    // start of the first message not taken out of the buffer yet
+    start: usize,
+    max_message_length: usize,
+    failed: bool,
+}
+
+impl StreamDecoder {
+    /// A decoder for the stream of `session`, usually the exporter's `SocketAddr`.
+    pub fn new<S: Into<SessionKey>>(session: S) -> StreamDecoder {
+        StreamDecoder {
+            session: session.into(),
+            buffer: Vec::new(),
+            start: 0,
+            max_message_length: MAX_MESSAGE_LENGTH,
+            failed: false,
+        }
+    }
+
+    /// Reject messages longer than `length` bytes, to bound the memory a connection can hold
+    /// on to. Messages can be up to 65535 bytes long by default.
+    pub fn with_max_message_length(mut self, length: u16) -> StreamDecoder {
+        self.max_message_length = length as usize;
+        self
+    }
+
+    pub fn session(&self) -> SessionKey {
+        self.session
+    }
+
+    /// Bytes received but not taken out as messages yet. Anything left when the connection
+    /// closes is a truncated message.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// True once the stream could not be split into messages; nothing comes out of the decoder
+    /// after that.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Add bytes read from the stream.
+    pub fn push(&mut self, data: &[u8]) {
+        if self.failed {
+            return;
+        }
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Take out the next complete message, or `None` until more of it has been pushed.
+    pub fn next_frame(&mut self) -> Result<Option<&[u8]>, IpfixError> {
+        let available = &self.buffer[self.start..];
+        if self.failed || available.len() < 4 {
+            return Ok(None);
+        }
+
+        // check the header as soon as the version and length are in, rather than waiting on
+        // the rest of a message that was never sent
+        let version = be_buf_to_u16(&available[..2]).unwrap_or(0);
+        let length = get_message_length(available)? as usize;
+        let error = if version != IPFIX_VERSION {
+            Some(IpfixError::BadVersion {
+                offset: 0,
+                set_id: None,
+                version,
+            })
+        } else if length < IPFIX_HEADER_LENGTH || length > self.max_message_length {
+            Some(IpfixError::InvalidMessageLength {
+                offset: 2,
+                set_id: None,
+                length,
+            })
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            self.failed = true;
+            self.buffer = Vec::new();
+            self.start = 0;
+            return Err(error);
+        }
+
+        if available.len() < length {
+            return Ok(None);
+        }
+        let start = self.start;
+        self.start += length;
+        Ok(Some(&self.buffer[start..start + length]))
+    }
+
+    /// Take out the next complete message and parse it with `consumer`, which is told the
+    /// session is a stream (see `IpfixConsumer::open_stream`). Returns `None` until more of the
+    /// message has been pushed. Errors parsing a message don't stop the decoder, unlike errors
+    /// splitting the stream; `TemplateRedefined` does mean the connection should be closed.
+    pub fn next_message(&mut self,
+                        consumer: &mut IpfixConsumer)
+                        -> Option<Result<ParsedMessage<'static>, IpfixError>> {
+        let session = self.session;
+        match self.next_frame() {
+            Ok(Some(message)) => {
+                consumer.open_stream(session);
+                Some(consumer.parse_message(session, message).map(ParsedMessage::into_owned))
+            }
+            Ok(None) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
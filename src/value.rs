@@ -0,0 +1,355 @@
+use std::borrow::Cow;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::conversions::*;
+use super::error::ValueError;
+use super::registry::DataType;
+
+// field length in a template that marks a variable-length Information Element (RFC 7011 §7)
+const VARIABLE_LENGTH: u16 = 65535;
+
+// seconds from the NTP epoch (1900) to the UNIX epoch, for dateTimeMicroseconds and
+// dateTimeNanoseconds
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// The value of an Information Element, decoded according to its abstract data type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue<'a> {
+    Unsigned8(u8),
+    Unsigned16(u16),
+    Unsigned32(u32),
+    Unsigned64(u64),
+    Signed8(i8),
+    Signed16(i16),
+    Signed32(i32),
+    Signed64(i64),
+    Float32(f32),
+    Float64(f64),
+    Boolean(bool),
+    MacAddress([u8; 6]),
+    OctetArray(&'a [u8]),
+    /// UTF-8 text; invalid sequences are replaced with U+FFFD
+    String(Cow<'a, str>),
+    DateTimeSeconds(SystemTime),
+    DateTimeMilliseconds(SystemTime),
+    DateTimeMicroseconds(SystemTime),
+    DateTimeNanoseconds(SystemTime),
+    Ipv4Address(Ipv4Addr),
+    Ipv6Address(Ipv6Addr),
+}
+
+impl<'a> FieldValue<'a> {
+    /// Decode `data` as a value of `data_type`. Integers may use reduced-size encoding, and a
+    /// float64 may be sent as a float32 (RFC 7011 §6.2). Structured lists are returned as octet
+    /// arrays, as decoding them takes templates; `DataRecord::list` has them decoded.
+    pub fn decode(data_type: DataType, data: &'a [u8]) -> Result<FieldValue<'a>, ValueError> {
+        let invalid = ValueError::InvalidLength {
+            data_type,
+            length: data.len(),
+        };
+        // a time the platform's SystemTime can't hold
+        let out_of_range = ValueError::OutOfRange {
+            data_type,
+            length: data.len(),
+        };
+        let value = match data_type {
+            DataType::OctetArray |
+            DataType::BasicList |
+            DataType::SubTemplateList |
+            DataType::SubTemplateMultiList => FieldValue::OctetArray(data),
+            DataType::Unsigned8 => FieldValue::Unsigned8(unsigned(data, 1).ok_or(invalid)? as u8),
+            DataType::Unsigned16 => {
+                FieldValue::Unsigned16(unsigned(data, 2).ok_or(invalid)? as u16)
+            }
+            DataType::Unsigned32 => {
+                FieldValue::Unsigned32(unsigned(data, 4).ok_or(invalid)? as u32)
+            }
+            DataType::Unsigned64 => FieldValue::Unsigned64(unsigned(data, 8).ok_or(invalid)?),
+            DataType::Signed8 => FieldValue::Signed8(signed(data, 1).ok_or(invalid)? as i8),
+            DataType::Signed16 => FieldValue::Signed16(signed(data, 2).ok_or(invalid)? as i16),
+            DataType::Signed32 => FieldValue::Signed32(signed(data, 4).ok_or(invalid)? as i32),
+            DataType::Signed64 => FieldValue::Signed64(signed(data, 8).ok_or(invalid)?),
+            DataType::Float32 => {
+                let bits = sized(data, 4).and_then(be_buf_to_u32).ok_or(invalid)?;
+                FieldValue::Float32(f32::from_bits(bits))
+            }
+            DataType::Float64 => {
+                if data.len() == 4 {
+                    let bits = be_buf_to_u32(data).ok_or(invalid)?;
+                    FieldValue::Float64(f32::from_bits(bits) as f64)
+                } else {
+                    let bits = sized(data, 8).and_then(be_buf_to_u64).ok_or(invalid)?;
+                    FieldValue::Float64(f64::from_bits(bits))
+                }
+            }
+            // RFC 7011 §6.1.5: true is 1 and false is 2
+            DataType::Boolean => {
+                match *sized(data, 1).ok_or(invalid)? {
+                    [1] => FieldValue::Boolean(true),
+                    [2] => FieldValue::Boolean(false),
+                    _ => return Err(ValueError::InvalidBoolean(data[0])),
+                }
+            }
+            DataType::MacAddress => {
+                let mut address = [0u8; 6];
+                address.copy_from_slice(sized(data, 6).ok_or(invalid)?);
+                FieldValue::MacAddress(address)
+            }
+            DataType::String => FieldValue::String(String::from_utf8_lossy(data)),
+            DataType::DateTimeSeconds => {
+                let seconds = sized(data, 4).and_then(be_buf_to_u32).ok_or(invalid)? as u64;
+                let time = UNIX_EPOCH.checked_add(Duration::from_secs(seconds));
+                FieldValue::DateTimeSeconds(time.ok_or(out_of_range)?)
+            }
+            DataType::DateTimeMilliseconds => {
+                let milliseconds = sized(data, 8).and_then(be_buf_to_u64).ok_or(invalid)?;
+                let time = UNIX_EPOCH.checked_add(Duration::from_millis(milliseconds));
+                FieldValue::DateTimeMilliseconds(time.ok_or(out_of_range)?)
+            }
+            DataType::DateTimeMicroseconds => {
+                // the low 11 bits of the fraction are below microsecond precision (RFC 7011
+                // §6.1.9) and are ignored
+                let (seconds, fraction) = ntp_timestamp(data).ok_or(invalid)?;
+                let time = ntp_time(seconds, fraction & !0x7FF, 1_000).ok_or(out_of_range)?;
+                FieldValue::DateTimeMicroseconds(time)
+            }
+            DataType::DateTimeNanoseconds => {
+                let (seconds, fraction) = ntp_timestamp(data).ok_or(invalid)?;
+                let time = ntp_time(seconds, fraction, 1).ok_or(out_of_range)?;
+                FieldValue::DateTimeNanoseconds(time)
+            }
+            DataType::Ipv4Address => {
+                let data = sized(data, 4).ok_or(invalid)?;
+                FieldValue::Ipv4Address(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+            }
+            DataType::Ipv6Address => {
+                let mut address = [0u8; 16];
+                address.copy_from_slice(sized(data, 16).ok_or(invalid)?);
+                FieldValue::Ipv6Address(Ipv6Addr::from(address))
+            }
+        };
+        Ok(value)
+    }
+
+    /// The abstract data type the value is decoded as.
+    pub fn data_type(&self) -> DataType {
+        match *self {
+            FieldValue::Unsigned8(_) => DataType::Unsigned8,
+            FieldValue::Unsigned16(_) => DataType::Unsigned16,
+            FieldValue::Unsigned32(_) => DataType::Unsigned32,
+            FieldValue::Unsigned64(_) => DataType::Unsigned64,
+            FieldValue::Signed8(_) => DataType::Signed8,
+            FieldValue::Signed16(_) => DataType::Signed16,
+            FieldValue::Signed32(_) => DataType::Signed32,
+            FieldValue::Signed64(_) => DataType::Signed64,
+            FieldValue::Float32(_) => DataType::Float32,
+            FieldValue::Float64(_) => DataType::Float64,
+            FieldValue::Boolean(_) => DataType::Boolean,
+            FieldValue::MacAddress(_) => DataType::MacAddress,
+            FieldValue::OctetArray(_) => DataType::OctetArray,
+            FieldValue::String(_) => DataType::String,
+            FieldValue::DateTimeSeconds(_) => DataType::DateTimeSeconds,
+            FieldValue::DateTimeMilliseconds(_) => DataType::DateTimeMilliseconds,
+            FieldValue::DateTimeMicroseconds(_) => DataType::DateTimeMicroseconds,
+            FieldValue::DateTimeNanoseconds(_) => DataType::DateTimeNanoseconds,
+            FieldValue::Ipv4Address(_) => DataType::Ipv4Address,
+            FieldValue::Ipv6Address(_) => DataType::Ipv6Address,
+        }
+    }
+
+    /// Append the value to `out`, encoded for a field of `length` bytes, the reverse of
+    /// `decode`. Integers are sent in fewer bytes than their type when `length` is shorter, as
+    /// long as the value fits, and a float64 is sent as a float32 in 4 bytes. Strings and octet
+    /// arrays must be exactly `length` bytes long, or have any length up to 65534 bytes when
+    /// `length` is 65535 (variable-length).
+    pub fn encode(&self, length: u16, out: &mut Vec<u8>) -> Result<(), ValueError> {
+        let data_type = self.data_type();
+        let invalid = ValueError::InvalidLength {
+            data_type,
+            length: length as usize,
+        };
+        let out_of_range = ValueError::OutOfRange {
+            data_type,
+            length: length as usize,
+        };
+        let length = length as usize;
+
+        // (value, size of the type, signed)
+        let integer = match *self {
+            FieldValue::Unsigned8(value) => Some((value as i128, 1, false)),
+            FieldValue::Unsigned16(value) => Some((value as i128, 2, false)),
+            FieldValue::Unsigned32(value) => Some((value as i128, 4, false)),
+            FieldValue::Unsigned64(value) => Some((value as i128, 8, false)),
+            FieldValue::Signed8(value) => Some((value as i128, 1, true)),
+            FieldValue::Signed16(value) => Some((value as i128, 2, true)),
+            FieldValue::Signed32(value) => Some((value as i128, 4, true)),
+            FieldValue::Signed64(value) => Some((value as i128, 8, true)),
+            _ => None,
+        };
+        if let Some((value, size, signed)) = integer {
+            if length == 0 || length > size {
+                return Err(invalid);
+            }
+            let bits = 8 * length as u32;
+            let (min, max) = if signed {
+                (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+            } else {
+                (0, (1 << bits) - 1)
+            };
+            if value < min || value > max {
+                return Err(out_of_range);
+            }
+            out.extend_from_slice(&value.to_be_bytes()[16 - length..]);
+            return Ok(());
+        }
+
+        match *self {
+            FieldValue::Float32(value) if length == 4 => {
+                out.extend_from_slice(&value.to_be_bytes())
+            }
+            FieldValue::Float64(value) if length == 8 => {
+                out.extend_from_slice(&value.to_be_bytes())
+            }
+            FieldValue::Float64(value) if length == 4 => {
+                out.extend_from_slice(&(value as f32).to_be_bytes())
+            }
+            FieldValue::Boolean(value) if length == 1 => out.push(if value { 1 } else { 2 }),
+            FieldValue::MacAddress(address) if length == 6 => out.extend_from_slice(&address),
+            FieldValue::OctetArray(data) => {
+                if !put_octets(out, data, length) {
+                    return Err(invalid);
+                }
+            }
+            FieldValue::String(ref value) => {
+                if !put_octets(out, value.as_bytes(), length) {
+                    return Err(invalid);
+                }
+            }
+            FieldValue::DateTimeSeconds(time) if length == 4 => {
+                let seconds = time.duration_since(UNIX_EPOCH).map_err(|_| out_of_range)?.as_secs();
+                if seconds > u32::MAX as u64 {
+                    return Err(out_of_range);
+                }
+                out.extend_from_slice(&(seconds as u32).to_be_bytes());
+            }
+            FieldValue::DateTimeMilliseconds(time) if length == 8 => {
+                let since = time.duration_since(UNIX_EPOCH).map_err(|_| out_of_range)?;
+                if since.as_millis() > u64::MAX as u128 {
+                    return Err(out_of_range);
+                }
+                out.extend_from_slice(&(since.as_millis() as u64).to_be_bytes());
+            }
+            FieldValue::DateTimeMicroseconds(time) if length == 8 => {
+                // the low 11 bits of the fraction are below microsecond precision, and sent as 0
+                let (seconds, fraction) = ntp_parts(time, 0x7FF).ok_or(out_of_range)?;
+                out.extend_from_slice(&seconds.to_be_bytes());
+                out.extend_from_slice(&fraction.to_be_bytes());
+            }
+            FieldValue::DateTimeNanoseconds(time) if length == 8 => {
+                let (seconds, fraction) = ntp_parts(time, 0).ok_or(out_of_range)?;
+                out.extend_from_slice(&seconds.to_be_bytes());
+                out.extend_from_slice(&fraction.to_be_bytes());
+            }
+            FieldValue::Ipv4Address(address) if length == 4 => {
+                out.extend_from_slice(&address.octets())
+            }
+            FieldValue::Ipv6Address(address) if length == 16 => {
+                out.extend_from_slice(&address.octets())
+            }
+            _ => return Err(invalid),
+        }
+        Ok(())
+    }
+}
+
+// the value, if it has exactly the length its type is encoded in
+#[inline]
+fn sized(data: &[u8], length: usize) -> Option<&[u8]> {
+    if data.len() == length {
+        Some(data)
+    } else {
+        None
+    }
+}
+
+// an unsigned integer of `size` bytes, sent in as many or fewer
+#[inline]
+fn unsigned(data: &[u8], size: usize) -> Option<u64> {
+    if data.len() > size {
+        return None;
+    }
+    be_buf_to_uint(data)
+}
+
+// a signed integer of `size` bytes, sent in as many or fewer and sign-extended back to its size
+#[inline]
+fn signed(data: &[u8], size: usize) -> Option<i64> {
+    let value = unsigned(data, size)?;
+    let shift = 64 - 8 * data.len();
+    Some(((value << shift) as i64) >> shift)
+}
+
+// Strings and octet arrays: as they are in a field of `length` bytes, or after their length in a
+// variable-length field (RFC 7011 §7), which takes one byte below 255 and 255 then two bytes
+// from there on
+#[inline]
+fn put_octets(out: &mut Vec<u8>, data: &[u8], length: usize) -> bool {
+    if length == VARIABLE_LENGTH as usize {
+        if data.len() < 255 {
+            out.push(data.len() as u8);
+        } else if data.len() < VARIABLE_LENGTH as usize {
+            out.push(255);
+            out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        } else {
+            return false;
+        }
+    } else if data.len() != length {
+        return false;
+    }
+    out.extend_from_slice(data);
+    true
+}
+
+#[inline]
+fn ntp_timestamp(data: &[u8]) -> Option<(u32, u32)> {
+    let data = sized(data, 8)?;
+    Some((be_buf_to_u32(&data[0..4])?, be_buf_to_u32(&data[4..8])?))
+}
+
+// The NTP seconds and fraction of a time, with the `ignored` low bits of the fraction cleared.
+// The fraction is rounded up, less than half a unit of the data type, so that ntp_time gives the
+// time back.
+#[inline]
+fn ntp_parts(time: SystemTime, ignored: u64) -> Option<(u32, u32)> {
+    let since_ntp_epoch = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.checked_add(Duration::from_secs(NTP_UNIX_OFFSET))?,
+        Err(before) => Duration::from_secs(NTP_UNIX_OFFSET).checked_sub(before.duration())?,
+    };
+    let mut seconds = since_ntp_epoch.as_secs();
+    let mut fraction = ((since_ntp_epoch.subsec_nanos() as u64) << 32).div_ceil(1_000_000_000);
+    fraction = (fraction + ignored) & !ignored;
+    if fraction >> 32 != 0 {
+        seconds += 1;
+        fraction = 0;
+    }
+    if seconds > u32::MAX as u64 {
+        return None;
+    }
+    Some((seconds as u32, fraction as u32))
+}
+
+// The time of an NTP timestamp, rounded to the nearest `unit` nanoseconds, or None when
+// SystemTime can't hold it
+#[inline]
+fn ntp_time(seconds: u32, fraction: u32, unit: u64) -> Option<SystemTime> {
+    let units = (fraction as u64 * 1_000_000_000 + (unit << 31)) / (unit << 32);
+    let since_ntp_epoch = Duration::from_secs(seconds as u64) +
+                          Duration::from_nanos(units * unit);
+    let offset = Duration::from_secs(NTP_UNIX_OFFSET);
+    if since_ntp_epoch >= offset {
+        UNIX_EPOCH.checked_add(since_ntp_epoch - offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset - since_ntp_epoch)
+    }
+}
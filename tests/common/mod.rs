@@ -0,0 +1,20 @@
+// Helpers shared by the integration tests. Each test file is a crate of its own and only uses
+// some of them.
+#![allow(dead_code)]
+
+/// Wraps `sets` in the header of an IPFIX message from Observation Domain 0, with sequence
+/// number 0 and a correct length.
+pub fn message(sets: &[u8]) -> Vec<u8> {
+    message_in(0, 0, sets)
+}
+
+/// Wraps `sets` in the header of an IPFIX message from an Observation Domain, with the given
+/// sequence number and a correct length.
+pub fn message_in(observation_domain_id: u32, sequence_number: u32, sets: &[u8]) -> Vec<u8> {
+    let length = (16 + sets.len()) as u16;
+    let mut bytes = vec![0x00, 0x0A, (length >> 8) as u8, length as u8, 0x58, 0x34, 0x94, 0xCA];
+    bytes.extend_from_slice(&sequence_number.to_be_bytes());
+    bytes.extend_from_slice(&observation_domain_id.to_be_bytes());
+    bytes.extend_from_slice(sets);
+    bytes
+}
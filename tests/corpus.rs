@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::io::prelude::*;
+    use std::fs::{self, File};
+    use self::ipfix::{get_message_length, is_template_set, IpfixConsumer, IpfixPrinter};
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    // Replays every input in tests/corpus the same way the parse_message fuzz target does: as a
+    // stream of messages sharing one consumer. Each file is an input that used to panic or hang.
+    fn replay(data: &[u8]) {
+        let mut consumer = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+
+        let _ = is_template_set(data);
+
+        let mut remaining = data;
+        while let Ok(length) = get_message_length(remaining) {
+            let length = if length == 0 {
+                remaining.len()
+            } else {
+                (length as usize).min(remaining.len())
+            };
+            let (message, rest) = remaining.split_at(length);
+            if let Ok(parsed) = consumer.parse_message(SESSION, message) {
+                for dataset in parsed.sets {
+                    printer.print_json(dataset);
+                }
+            }
+            remaining = rest;
+        }
+    }
+
+    #[test]
+    fn test_corpus() {
+        let mut count = 0;
+        for entry in fs::read_dir("tests/corpus").unwrap() {
+            let path = entry.unwrap().path();
+            let mut data = Vec::new();
+            File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+            replay(&data);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_invalid_lengths_print_null() {
+        let mut data = Vec::new();
+        File::open("tests/corpus/short_address_fields.bin")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let (template, data) = data.split_at(get_message_length(&data).unwrap() as usize);
+
+        let mut consumer = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+        consumer.parse_message(SESSION, template).unwrap();
+        let mut message = consumer.parse_message(SESSION, data).unwrap();
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"sourceIPv4Address":null,"destinationIPv4Address":null,"sourceIPv6Address":null}"#]);
+    }
+}
@@ -0,0 +1,166 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use self::ipfix::{IpfixConsumer, IpfixError};
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    // template 256: sourceIPv4Address (4), destinationIPv4Address (4)
+    const TEMPLATE_SET: [u8; 16] = [0x00, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x02, 0x00, 0x08,
+                                    0x00, 0x04, 0x00, 0x0C, 0x00, 0x04];
+
+    const DATA_SET: [u8; 12] = [0x01, 0x00, 0x00, 0x0C, 0x0A, 0x00, 0x00, 0x01, 0x0A, 0x00, 0x00,
+                                0x02];
+
+    #[test]
+    fn test_bad_version() {
+        let mut bytes = message(&TEMPLATE_SET);
+        // NetFlow v8, unlike v9, is not supported
+        bytes[1] = 0x08;
+        let err = IpfixConsumer::new().parse_message(SESSION, &bytes).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::BadVersion {
+                       offset: 0,
+                       set_id: None,
+                       version: 8,
+                   });
+        assert_eq!(err.set_id(), None);
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let mut bytes = message(&TEMPLATE_SET);
+        bytes.push(0x00);
+        let err = IpfixConsumer::new().parse_message(SESSION, &bytes).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::LengthMismatch {
+                       offset: 2,
+                       set_id: None,
+                       expected: 32,
+                       actual: 33,
+                   });
+
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        let bytes = message(&TEMPLATE_SET);
+        let err = IpfixConsumer::new().parse_message(SESSION, &bytes[0..10]).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::TruncatedHeader {
+                       offset: 0,
+                       set_id: None,
+                       length: 16,
+                       available: 10,
+                   });
+        assert!(matches!(ipfix::get_message_length(&bytes[0..3]),
+                         Err(IpfixError::TruncatedHeader { length: 4, available: 3, .. })));
+        assert!(matches!(ipfix::is_template_set(&bytes[0..16]),
+                         Err(IpfixError::TruncatedHeader { length: 18, available: 16, .. })));
+    }
+
+    #[test]
+    fn test_truncated_set() {
+        let mut set = TEMPLATE_SET.to_vec();
+        set[3] = 0x20;
+        let err = IpfixConsumer::new().parse_message(SESSION, &message(&set)).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::TruncatedSet {
+                       offset: 16,
+                       set_id: Some(2),
+                       length: 32,
+                       available: 16,
+                   });
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        // template 257: sourceIPv4Address (variable), protocolIdentifier (1)
+        let template = [0x00, 0x02, 0x00, 0x10, 0x01, 0x01, 0x00, 0x02, 0x00, 0x08, 0xFF, 0xFF,
+                        0x00, 0x04, 0x00, 0x01];
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+
+        // a byte too short to hold another record is padding
+        let data = [0x01, 0x01, 0x00, 0x0B, 0x04, 0x0A, 0x00, 0x00, 0x01, 0x06, 0x00];
+        let bytes = message(&data);
+        assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sets[0].records().len(), 1);
+
+        // but the second record claims a 4 byte address with 3 bytes left
+        let data = [0x01, 0x01, 0x00, 0x0E, 0x04, 0x0A, 0x00, 0x00, 0x01, 0x06, 0x04, 0x0A, 0x00,
+                    0x00];
+        let err = parser.parse_message(SESSION, &message(&data)).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::TruncatedSet {
+                       offset: 26,
+                       set_id: Some(257),
+                       length: 5,
+                       available: 4,
+                   });
+    }
+
+    #[test]
+    fn test_variable_length_padding() {
+        // template 258: interfaceName (variable)
+        let template = [0x00, 0x02, 0x00, 0x0C, 0x01, 0x02, 0x00, 0x01, 0x00, 0x52, 0xFF, 0xFF];
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+
+        // "eth0", then zeros that would otherwise each decode as an empty name
+        for padding in 1..4 {
+            let mut data = vec![0x01, 0x02, 0x00, 9 + padding, 0x04, 0x65, 0x74, 0x68, 0x30];
+            data.resize(9 + padding as usize, 0x00);
+            let bytes = message(&data);
+            let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+            let records = parsed.sets[0].records();
+            assert_eq!(records.len(), 1, "{} bytes of padding", padding);
+            assert_eq!(records[0].value(82), Some(&b"eth0"[..]));
+        }
+    }
+
+    #[test]
+    fn test_short_set_length() {
+        let mut set = TEMPLATE_SET.to_vec();
+        set[3] = 0x02;
+        let err = IpfixConsumer::new().parse_message(SESSION, &message(&set)).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::ShortSetLength {
+                       offset: 16,
+                       set_id: Some(2),
+                       length: 2,
+                   });
+    }
+
+    #[test]
+    fn test_unknown_template() {
+        let mut parser = IpfixConsumer::new();
+        let err = parser.parse_message(SESSION, &message(&DATA_SET)).err().unwrap();
+        assert!(err.is_missing_template());
+        assert_eq!(err.offset(), 16);
+        assert_eq!(err.set_id(), Some(256));
+
+        assert!(parser.parse_message(SESSION, &message(&TEMPLATE_SET)).is_ok());
+        let bytes = message(&DATA_SET);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(message.sets.len(), 1);
+    }
+
+    #[test]
+    fn test_malformed_field_specifier() {
+        // claims three field specifiers but only carries two
+        let mut set = TEMPLATE_SET.to_vec();
+        set[7] = 0x03;
+        let err = IpfixConsumer::new().parse_message(SESSION, &message(&set)).err().unwrap();
+        assert_eq!(err,
+                   IpfixError::MalformedFieldSpecifier {
+                       offset: 32,
+                       set_id: Some(2),
+                   });
+        assert!(!err.is_missing_template());
+    }
+}
@@ -0,0 +1,181 @@
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::borrow::Cow;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::{Duration, UNIX_EPOCH};
+    use self::ipfix::{ElementId, ExportError, FieldSpecifier, FieldValue, IpfixConsumer,
+                      IpfixExporter, ValueError};
+
+    // all messages in these tests go to the same collector
+    const SESSION: u64 = 0;
+
+    #[test]
+    fn test_round_trip() {
+        let mut exporter = IpfixExporter::new();
+        // octetDeltaCount (8), sourceIPv6Address (16), sourceMacAddress (6), interfaceName
+        // (variable), flowStartMicroseconds (8), packetDeltaCount (2), and ntop element 118 (2)
+        let flows = exporter.add_template(&[FieldSpecifier::new(1, 8),
+                                            FieldSpecifier::new(27, 16),
+                                            FieldSpecifier::new(56, 6),
+                                            FieldSpecifier::new(82, 65535),
+                                            FieldSpecifier::new(154, 8),
+                                            FieldSpecifier::new(2, 2),
+                                            FieldSpecifier::new(ElementId::enterprise(35632,
+                                                                                      118),
+                                                                2)])
+            .unwrap();
+        // scope: observationDomainId (4); exportedMessageTotalCount (8)
+        let stats = exporter.add_options_template(&[FieldSpecifier::new(149, 4)],
+                                  &[FieldSpecifier::new(41, 8)])
+            .unwrap();
+        assert_eq!((flows, stats), (256, 257));
+
+        let start = UNIX_EPOCH + Duration::new(1500000000, 123456000);
+        let long_name = "x".repeat(300);
+        let mut message = exporter.message(7);
+        message.export_time(1500000100)
+            .templates()
+            .record(flows,
+                    &[FieldValue::Unsigned64(1 << 40),
+                      FieldValue::Ipv6Address(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                      FieldValue::MacAddress([0x00, 0x1B, 0x21, 0x01, 0x02, 0x03]),
+                      FieldValue::String(Cow::Borrowed("eth0")),
+                      FieldValue::DateTimeMicroseconds(start),
+                      FieldValue::Unsigned64(3),
+                      FieldValue::Unsigned16(7)])
+            .unwrap()
+            .record(flows,
+                    &[FieldValue::Unsigned64(0),
+                      FieldValue::Ipv6Address(Ipv6Addr::LOCALHOST),
+                      FieldValue::MacAddress([0; 6]),
+                      FieldValue::String(Cow::Borrowed(&long_name)),
+                      FieldValue::DateTimeMicroseconds(UNIX_EPOCH),
+                      FieldValue::Unsigned64(65535),
+                      FieldValue::Unsigned16(0)])
+            .unwrap()
+            .record(stats, &[FieldValue::Unsigned32(7), FieldValue::Unsigned64(1)])
+            .unwrap();
+        let bytes = message.finish().unwrap();
+
+        let mut consumer = IpfixConsumer::new();
+        let parsed = consumer.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(parsed.header.version, 10);
+        assert_eq!(parsed.header.length as usize, bytes.len());
+        assert_eq!(parsed.header.export_time, 1500000100);
+        assert_eq!(parsed.header.sequence_number, 0);
+        assert_eq!(parsed.header.observation_domain_id, 7);
+
+        let template_ids: Vec<u16> = parsed.sets.iter().map(|set| set.template_id()).collect();
+        assert_eq!(template_ids, vec![256, 257]);
+        let records = parsed.sets[0].records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get(1), Some(Ok(FieldValue::Unsigned64(1 << 40))));
+        assert_eq!(records[0].get(27),
+                   Some(Ok(FieldValue::Ipv6Address(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0,
+                                                                 1)))));
+        assert_eq!(records[0].get(56),
+                   Some(Ok(FieldValue::MacAddress([0x00, 0x1B, 0x21, 0x01, 0x02, 0x03]))));
+        assert_eq!(records[0].get(82), Some(Ok(FieldValue::String(Cow::Borrowed("eth0")))));
+        assert_eq!(records[0].get(154), Some(Ok(FieldValue::DateTimeMicroseconds(start))));
+        assert_eq!(records[0].value(2), Some(&[0x00, 0x03][..]));
+        assert_eq!(records[0].value(ElementId::enterprise(35632, 118)),
+                   Some(&[0x00, 0x07][..]));
+        assert_eq!(records[1].get(82),
+                   Some(Ok(FieldValue::String(Cow::Borrowed(long_name.as_str())))));
+        assert_eq!(records[1].get(154),
+                   Some(Ok(FieldValue::DateTimeMicroseconds(UNIX_EPOCH))));
+        assert_eq!(records[1].get(2), Some(Ok(FieldValue::Unsigned64(65535))));
+        assert_eq!(parsed.sets[1].records()[0].get(149),
+                   Some(Ok(FieldValue::Unsigned32(7))));
+        assert_eq!(parsed.sets[1].records()[0].get(41),
+                   Some(Ok(FieldValue::Unsigned64(1))));
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        let mut exporter = IpfixExporter::new().with_first_template_id(1000);
+        let template = exporter.add_template(&[FieldSpecifier::new(8, 4)]).unwrap();
+        assert_eq!(template, 1000);
+        let address = [FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 1))];
+
+        let mut consumer = IpfixConsumer::new();
+        for (count, expected) in [(2, 0), (0, 2), (3, 2), (1, 5)].iter() {
+            let mut message = exporter.message(1);
+            message.templates();
+            for _ in 0..*count {
+                message.record(template, &address).unwrap();
+            }
+            let bytes = message.finish().unwrap();
+            let parsed = consumer.parse_message(SESSION, &bytes).unwrap();
+            assert_eq!(parsed.header.sequence_number, *expected);
+            assert_eq!(parsed.sequence_event, None);
+        }
+
+        // each Observation Domain has a sequence of its own
+        let mut message = exporter.message(2);
+        message.template(template).unwrap().record(template, &address).unwrap();
+        let bytes = message.finish().unwrap();
+        let parsed = consumer.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(parsed.header.sequence_number, 0);
+        let stats = consumer.sequence_stats(SESSION, 1).unwrap();
+        assert_eq!((stats.records, stats.gaps), (6, 0));
+    }
+
+    #[test]
+    fn test_export_errors() {
+        let mut exporter = IpfixExporter::new();
+        assert_eq!(exporter.add_template(&[]), Err(ExportError::InvalidTemplate));
+        assert_eq!(exporter.add_template(&[FieldSpecifier::new(0x8000, 4)]),
+                   Err(ExportError::InvalidTemplate));
+        assert_eq!(exporter.add_options_template(&[], &[FieldSpecifier::new(41, 8)]),
+                   Err(ExportError::InvalidTemplate));
+        // records of zero-length fields only would be empty
+        assert_eq!(exporter.add_template(&[FieldSpecifier::new(1, 0), FieldSpecifier::new(2, 0)]),
+                   Err(ExportError::InvalidTemplate));
+        assert_eq!(exporter.add_options_template(&[FieldSpecifier::new(149, 0)], &[]),
+                   Err(ExportError::InvalidTemplate));
+        // octetDeltaCount (4), interfaceName (variable)
+        let template = exporter.add_template(&[FieldSpecifier::new(1, 4),
+                                               FieldSpecifier::new(82, 65535)])
+            .unwrap();
+        assert_eq!(template, 256);
+
+        let mut message = exporter.message(0);
+        assert_eq!(message.template(300).err(),
+                   Some(ExportError::UnknownTemplate(300)));
+        assert_eq!(message.record(300, &[]).err(), Some(ExportError::UnknownTemplate(300)));
+        assert_eq!(message.record(template, &[FieldValue::Unsigned64(1)]).err(),
+                   Some(ExportError::FieldCount {
+                       template_id: 256,
+                       expected: 2,
+                       actual: 1,
+                   }));
+        let name = FieldValue::String(Cow::Borrowed("eth0"));
+        assert_eq!(message.record(template, &[FieldValue::Unsigned64(1 << 32), name]).err(),
+                   Some(ExportError::InvalidValue {
+                       template_id: 256,
+                       field: 0,
+                       error: ValueError::OutOfRange {
+                           data_type: ipfix::DataType::Unsigned64,
+                           length: 4,
+                       },
+                   }));
+
+        // failed records leave nothing behind
+        let bytes = message.finish().unwrap();
+        assert_eq!(bytes.len(), 16);
+
+        let long_name = FieldValue::String(Cow::Owned("x".repeat(30000)));
+        for &(count, length) in [(2, Ok(60034)), (3, Err(ExportError::MessageTooLong(90041)))]
+            .iter() {
+            let mut message = exporter.message(0);
+            for value in 0..count {
+                message.record(template, &[FieldValue::Unsigned32(value), long_name.clone()])
+                    .unwrap();
+            }
+            assert_eq!(message.finish().map(|bytes| bytes.len()), length);
+        }
+    }
+}
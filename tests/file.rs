@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::io::Cursor;
+    use self::ipfix::{FieldSpecifier, FieldValue, FileReader, FileWriter, IpfixConsumer,
+                      IpfixExporter};
+
+    // octetDeltaCount (8), packetDeltaCount (4)
+    fn exporter() -> (IpfixExporter, u16) {
+        let mut exporter = IpfixExporter::new();
+        let template = exporter.add_template(&[FieldSpecifier::new(1, 8),
+                                               FieldSpecifier::new(2, 4)])
+            .unwrap();
+        (exporter, template)
+    }
+
+    fn data(exporter: &mut IpfixExporter, template: u16, octets: u64) -> Vec<u8> {
+        let mut message = exporter.message(1);
+        message.export_time(1500000000)
+            .record(template,
+                    &[FieldValue::Unsigned64(octets), FieldValue::Unsigned32(1)])
+            .unwrap();
+        message.finish().unwrap()
+    }
+
+    fn octets(file: Vec<u8>) -> Vec<u64> {
+        let mut octets = Vec::new();
+        for message in FileReader::new(Cursor::new(file)) {
+            for set in message.unwrap().sets {
+                for record in set.records() {
+                    match record.get(1) {
+                        Some(Ok(FieldValue::Unsigned64(value))) => octets.push(value),
+                        value => panic!("unexpected octetDeltaCount {:?}", value),
+                    }
+                }
+            }
+        }
+        octets
+    }
+
+    #[test]
+    fn test_read_file() {
+        let (mut exporter, template) = exporter();
+        let mut file = Vec::new();
+        let mut message = exporter.message(1);
+        message.templates();
+        file.extend(message.finish().unwrap());
+        for octets in 1..4 {
+            file.extend(data(&mut exporter, template, octets));
+        }
+
+        let mut reader = FileReader::new(Cursor::new(&file));
+        let sequence_numbers = reader.by_ref()
+            .map(|message| message.unwrap().header.sequence_number)
+            .collect::<Vec<_>>();
+        assert_eq!(sequence_numbers, vec![0, 0, 1, 2]);
+        assert!(!reader.is_truncated());
+        assert_eq!(reader.consumer().sequence_stats(0, 1).unwrap().records, 3);
+
+        assert_eq!(octets(file), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let (mut exporter, template) = exporter();
+        let mut message = exporter.message(1);
+        message.templates();
+        let mut file = message.finish().unwrap();
+        file.extend(data(&mut exporter, template, 1));
+        let complete = file.len();
+        file.extend(data(&mut exporter, template, 2));
+
+        for &(length, messages) in [(complete, 2), (complete + 10, 2), (complete + 30, 2),
+                                    (complete - 1, 1)]
+            .iter() {
+            let mut reader = FileReader::new(Cursor::new(&file[..length]));
+            assert_eq!(reader.by_ref().filter(|message| message.is_ok()).count(), messages);
+            assert_eq!(reader.is_truncated(), length != complete);
+        }
+
+        // a message length shorter than its header leaves no way to find the next message
+        let mut bad = file.clone();
+        bad[2..4].copy_from_slice(&[0x00, 0x08]);
+        let mut reader = FileReader::new(Cursor::new(bad));
+        let error = reader.next().unwrap().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_unknown_template() {
+        let (mut exporter, template) = exporter();
+        let mut file = data(&mut exporter, template, 1);
+        let mut message = exporter.message(1);
+        message.templates();
+        file.extend(message.finish().unwrap());
+        file.extend(data(&mut exporter, template, 2));
+
+        // the first message can't be decoded, the ones after it still are
+        let results = FileReader::new(Cursor::new(file))
+            .map(|message| message.map(|message| message.sets.len()).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![None, Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let (mut exporter, template) = exporter();
+        let mut message = exporter.message(1);
+        message.templates();
+        let templates = message.finish().unwrap();
+
+        let mut writer = FileWriter::new(Vec::new());
+        writer.write_message(&templates).unwrap();
+        writer.write_message(&data(&mut exporter, template, 1)).unwrap();
+        // templates are written once per file
+        assert_eq!(writer.get_ref().len(), templates.len() + 32);
+
+        // a new file starts with the templates its data needs, in a message with the header
+        // fields of the data's
+        let first = writer.rotate(Vec::new());
+        let message = data(&mut exporter, template, 2);
+        writer.write_message(&message).unwrap();
+        writer.write_message(&data(&mut exporter, template, 3)).unwrap();
+        let second = writer.into_inner();
+        assert_eq!(second.len(), templates.len() + 2 * 32);
+        assert_eq!(second[..templates.len()][4..16], message[4..16]);
+        assert_eq!(second[16..templates.len()], templates[16..]);
+
+        assert_eq!(octets(first), vec![1]);
+        assert_eq!(octets(second), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_write_withdrawn_template() {
+        let (mut exporter, template) = exporter();
+        let mut message = exporter.message(1);
+        message.templates();
+        let mut writer = FileWriter::new(Vec::new());
+        writer.write_message(&message.finish().unwrap()).unwrap();
+
+        // withdrawing template 256 in a message of its own
+        let withdrawal = [0x00, 0x0A, 0x00, 0x18, 0x59, 0x68, 0x2F, 0x00, 0x00, 0x00, 0x00,
+                          0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x08, 0x01, 0x00,
+                          0x00, 0x00];
+        writer.write_message(&withdrawal).unwrap();
+        writer.rotate(Vec::new());
+
+        // data for a withdrawn template goes out as it is, without the old template
+        let message = data(&mut exporter, template, 1);
+        writer.write_message(&message).unwrap();
+        assert_eq!(writer.get_ref(), &message);
+
+        let mut consumer = IpfixConsumer::new();
+        assert!(consumer.parse_message(0, &message).is_err());
+        assert!(writer.write_message(&message[..20]).is_err());
+        assert_eq!(writer.get_ref().len(), message.len());
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+    extern crate serde_json;
+
+    use std::fs::File;
+    use std::io::Read;
+    use self::ipfix::{IpfixConsumer, IpfixPrinter};
+    use self::serde_json::{json, Value};
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    // Every record of every data set in `sets`, printed and parsed back by a strict JSON parser
+    fn print(template: &[u8], sets: &[u8]) -> Vec<Value> {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(template)).unwrap();
+        let bytes = message(sets);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        let printer = IpfixPrinter::new();
+        message.sets
+            .into_iter()
+            .flat_map(|set| printer.print_json(set))
+            .map(|record| serde_json::from_str(&record).expect(&record))
+            .collect()
+    }
+
+    // a data set for template 256 holding one record of a single variable-length field
+    fn variable_length_set(value: &[u8]) -> Vec<u8> {
+        let length = (5 + value.len()) as u16;
+        let mut set = vec![0x01, 0x00, (length >> 8) as u8, length as u8, value.len() as u8];
+        set.extend_from_slice(value);
+        set
+    }
+
+    #[test]
+    fn test_escaping() {
+        // template 256: interfaceName (variable)
+        let template = [0x00, 0x02, 0x00, 0x0C, 0x01, 0x00, 0x00, 0x01, 0x00, 0x52, 0xFF, 0xFF];
+        let names = ["say \"hi\"", "C:\\eth0", "tab\there\nand\r\u{1}\u{1F}", "\u{7F}ø€𝄞"];
+        for name in names.iter() {
+            let records = print(&template, &variable_length_set(name.as_bytes()));
+            assert_eq!(records, vec![json!({ "interfaceName": name })]);
+        }
+
+        // invalid UTF-8 is replaced rather than passed through
+        let records = print(&template, &variable_length_set(&[0x22, 0xFF, 0x5C]));
+        assert_eq!(records, vec![json!({ "interfaceName": "\"\u{FFFD}\\" })]);
+    }
+
+    #[test]
+    fn test_empty_record() {
+        // template 256: unassigned elements 1000 (4) and 1001 (2)
+        let template = [0x00, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x02, 0x03, 0xE8, 0x00, 0x04,
+                        0x03, 0xE9, 0x00, 0x02];
+        let data = [0x01, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00,
+                    0x03, 0x00, 0x04];
+        assert_eq!(print(&template, &data), vec![json!({}), json!({})]);
+    }
+
+    #[test]
+    fn test_values() {
+        // template 256: octetDeltaCount (8), sourceIPv6Address (16), sourceMacAddress (6),
+        // mplsTopLabelStackSection (3), flowStartMilliseconds (8), samplingProbability (8),
+        // dataRecordsReliability (1), ipHeaderPacketSection (variable), and sourceMacAddress
+        // again with an invalid length (2)
+        let template = [0x00, 0x02, 0x00, 0x2C, 0x01, 0x00, 0x00, 0x09, 0x00, 0x01, 0x00, 0x08,
+                        0x00, 0x1B, 0x00, 0x10, 0x00, 0x38, 0x00, 0x06, 0x00, 0x46, 0x00, 0x03,
+                        0x00, 0x98, 0x00, 0x08, 0x01, 0x37, 0x00, 0x08, 0x01, 0x14, 0x00, 0x01,
+                        0x01, 0x39, 0xFF, 0xFF, 0x00, 0x38, 0x00, 0x02];
+        let data = [0x01, 0x00, 0x00, 0x3C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0xDC, 0x20,
+                    0x01, 0x0D, 0xB8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x01, 0x00, 0x1B, 0x21, 0x01, 0x02, 0x03, 0x00, 0x06, 0x41, 0x00, 0x00,
+                    0x01, 0x5D, 0x3E, 0xF7, 0x98, 0x7B, 0x3F, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x01, 0x03, 0x45, 0x00, 0x14, 0xFF, 0xFF];
+        let records = print(&template, &data);
+        assert_eq!(records,
+                   vec![json!({
+                            "octetDeltaCount": 1500,
+                            "sourceIPv6Address": "2001:0db8:0000:0000:0000:0000:0000:0001",
+                            "sourceMacAddress": ["00:1b:21:01:02:03", null],
+                            "mplsTopLabelStackSection": { "label": 100, "exp": 0, "bottom": true },
+                            "flowStartMilliseconds": 1500000000123u64,
+                            "samplingProbability": 0.5,
+                            "dataRecordsReliability": true,
+                            "ipHeaderPacketSection": "450014",
+                        })]);
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let mut f = File::open("tests/string.txt").unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        // tests/string.txt holds the records back to back
+        let records = serde_json::Deserializer::from_str(&s)
+            .into_iter::<Value>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 21);
+        assert!(records.iter().all(Value::is_object));
+    }
+}
@@ -0,0 +1,195 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+    extern crate serde_json;
+
+    use std::net::Ipv4Addr;
+    use self::ipfix::{FieldValue, IpfixConsumer, IpfixPrinter, ListSemantic, StructuredList};
+    use self::serde_json::{json, Value};
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    fn set(set_id: u16, content: &[u8]) -> Vec<u8> {
+        let length = (4 + content.len()) as u16;
+        let mut bytes = vec![(set_id >> 8) as u8, set_id as u8, (length >> 8) as u8,
+                             length as u8];
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    // a variable-length field value, with its length in one byte
+    fn variable(value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![value.len() as u8];
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    // template 256: octetDeltaCount (4), basicList, subTemplateList, subTemplateMultiList
+    // template 257: sourceIPv4Address (4), destinationTransportPort (2)
+    // template 258: protocolIdentifier (1)
+    const TEMPLATE_SET: [u8; 48] = [0x00, 0x02, 0x00, 0x30, 0x01, 0x00, 0x00, 0x04, 0x00, 0x01,
+                                    0x00, 0x04, 0x01, 0x23, 0xFF, 0xFF, 0x01, 0x24, 0xFF, 0xFF,
+                                    0x01, 0x25, 0xFF, 0xFF, 0x01, 0x01, 0x00, 0x02, 0x00, 0x08,
+                                    0x00, 0x04, 0x00, 0x0B, 0x00, 0x02, 0x01, 0x02, 0x00, 0x01,
+                                    0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+
+    // allOf sourceIPv4Address 10.0.0.1 and 10.0.0.2
+    const BASIC_LIST: [u8; 13] = [0x03, 0x00, 0x08, 0x00, 0x04, 0x0A, 0x00, 0x00, 0x01, 0x0A,
+                                  0x00, 0x00, 0x02];
+
+    // exactlyOneOf 192.0.2.1 port 80 and 192.0.2.2 port 443, of template 257
+    const SUB_TEMPLATE_LIST: [u8; 15] = [0x01, 0x01, 0x01, 0xC0, 0x00, 0x02, 0x01, 0x00, 0x50,
+                                         0xC0, 0x00, 0x02, 0x02, 0x01, 0xBB];
+
+    // ordered 192.0.2.3 port 53 of template 257, then protocol 6 of template 258
+    const SUB_TEMPLATE_MULTI_LIST: [u8; 16] = [0x04, 0x01, 0x01, 0x00, 0x0A, 0xC0, 0x00, 0x02,
+                                               0x03, 0x00, 0x35, 0x01, 0x02, 0x00, 0x05, 0x06];
+
+    fn data_set(sub_template_list: &[u8]) -> Vec<u8> {
+        let mut record = vec![0x00, 0x00, 0x00, 0x64];
+        record.extend(variable(&BASIC_LIST));
+        record.extend(variable(sub_template_list));
+        record.extend(variable(&SUB_TEMPLATE_MULTI_LIST));
+        set(256, &record)
+    }
+
+    fn address(value: Option<Result<FieldValue, ipfix::ValueError>>) -> Ipv4Addr {
+        match value {
+            Some(Ok(FieldValue::Ipv4Address(address))) => address,
+            value => panic!("unexpected address {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_decode_lists() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&data_set(&SUB_TEMPLATE_LIST));
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &parsed.sets[0].records()[0];
+
+        // the raw value is still there
+        assert_eq!(record.value(291), Some(&BASIC_LIST[..]));
+        match record.list(291) {
+            Some(&StructuredList::Basic { semantic, field, ref values }) => {
+                assert_eq!(semantic, ListSemantic::AllOf);
+                assert_eq!(field.element_id().id, 8);
+                let addresses = values.values(8);
+                assert_eq!(addresses, vec![&[10, 0, 0, 1][..], &[10, 0, 0, 2][..]]);
+            }
+            _ => panic!("basicList not decoded"),
+        }
+
+        match record.list(292) {
+            Some(&StructuredList::SubTemplate { semantic, template_id, ref records }) => {
+                assert_eq!(semantic, ListSemantic::ExactlyOneOf);
+                assert_eq!(template_id, 257);
+                assert_eq!(records.len(), 2);
+                assert_eq!(address(records[1].get(8)), Ipv4Addr::new(192, 0, 2, 2));
+                assert_eq!(records[1].get(11), Some(Ok(FieldValue::Unsigned16(443))));
+            }
+            _ => panic!("subTemplateList not decoded"),
+        }
+
+        match record.list(293) {
+            Some(&StructuredList::SubTemplateMulti { semantic, ref lists }) => {
+                assert_eq!(semantic, ListSemantic::Ordered);
+                let lengths = lists.iter()
+                    .map(|&(id, ref records)| (id, records.len()))
+                    .collect::<Vec<_>>();
+                assert_eq!(lengths, vec![(257, 1), (258, 1)]);
+                assert_eq!(lists[1].1[0].get(4), Some(Ok(FieldValue::Unsigned8(6))));
+            }
+            _ => panic!("subTemplateMultiList not decoded"),
+        }
+
+        // lists outlive the message
+        let owned = parser.parse_message(SESSION, &bytes).unwrap().into_owned();
+        assert!(owned.sets[0].records()[0].list(293) == record.list(293));
+    }
+
+    #[test]
+    fn test_undecodable_lists() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+
+        // template 300 is unknown
+        let mut unknown = SUB_TEMPLATE_LIST;
+        unknown[1..3].copy_from_slice(&[0x01, 0x2C]);
+        let bytes = message(&data_set(&unknown));
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &parsed.sets[0].records()[0];
+        assert!(record.list(292).is_none());
+        assert_eq!(record.value(292), Some(&unknown[..]));
+        assert!(record.list(291).is_some());
+
+        // lists nest as deep as templates let them, but are only decoded so far: template 259
+        // holds nothing but a subTemplateList of itself
+        let template = set(2, &[0x01, 0x03, 0x00, 0x01, 0x01, 0x24, 0xFF, 0xFF]);
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let mut list = vec![0x03, 0x01, 0x03];
+        for _ in 0..20 {
+            let mut outer = vec![0x03, 0x01, 0x03];
+            outer.extend(variable(&list));
+            list = outer;
+        }
+        let bytes = message(&set(259, &variable(&list)));
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let mut depth = 0;
+        let mut record = &parsed.sets[0].records()[0];
+        while let Some(StructuredList::SubTemplate { records, .. }) = record.list(292) {
+            depth += 1;
+            record = &records[0];
+        }
+        assert_eq!(depth, 8);
+    }
+
+    #[test]
+    fn test_print_lists() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&data_set(&SUB_TEMPLATE_LIST));
+        let mut parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let json = IpfixPrinter::new().print_json(parsed.sets.remove(0));
+        let record: Value = serde_json::from_str(&json[0]).expect(&json[0]);
+        assert_eq!(record,
+                   json!({
+                       "octetDeltaCount": 100,
+                       "basicList": {
+                           "semantic": "allOf",
+                           "sourceIPv4Address": ["10.0.0.1", "10.0.0.2"],
+                       },
+                       "subTemplateList": {
+                           "semantic": "exactlyOneOf",
+                           "template_id": 257,
+                           "records": [
+                               {
+                                   "sourceIPv4Address": "192.0.2.1",
+                                   "destinationTransportPort": 80,
+                               },
+                               {
+                                   "sourceIPv4Address": "192.0.2.2",
+                                   "destinationTransportPort": 443,
+                               },
+                           ],
+                       },
+                       "subTemplateMultiList": {
+                           "semantic": "ordered",
+                           "lists": [
+                               {
+                                   "template_id": 257,
+                                   "records": [{
+                                       "sourceIPv4Address": "192.0.2.3",
+                                       "destinationTransportPort": 53,
+                                   }],
+                               },
+                               { "template_id": 258, "records": [{ "protocolIdentifier": 6 }] },
+                           ],
+                       },
+                   }));
+    }
+}
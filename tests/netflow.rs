@@ -0,0 +1,299 @@
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::borrow::Cow;
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, UNIX_EPOCH};
+    use self::ipfix::{ElementId, FieldValue, IpfixConsumer, IpfixError, IpfixPrinter,
+                      SequenceEvent};
+
+    const SESSION: u64 = 0;
+
+    // a NetFlow v9 packet from source id 1, 10 seconds after the exporter started
+    fn packet(sequence_number: u32, flowsets: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x09, 0x00, 0x01, 0x00, 0x00, 0x27, 0x10, 0x58, 0x34, 0x94,
+                             0xCA];
+        bytes.extend_from_slice(&sequence_number.to_be_bytes());
+        bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+        bytes.extend_from_slice(flowsets);
+        bytes
+    }
+
+    // template 256: IN_BYTES (4), IPV4_SRC_ADDR (4), FIRST_SWITCHED (4), LAST_SWITCHED (4), and
+    // the Cisco ASA type 33002 (2)
+    const TEMPLATE_FLOWSET: [u8; 28] = [0x00, 0x00, 0x00, 0x1C, 0x01, 0x00, 0x00, 0x05, 0x00,
+                                        0x01, 0x00, 0x04, 0x00, 0x08, 0x00, 0x04, 0x00, 0x16,
+                                        0x00, 0x04, 0x00, 0x15, 0x00, 0x04, 0x80, 0xEA, 0x00,
+                                        0x02];
+
+    // options template 257: scope System (4), option SAMPLING_INTERVAL (4), then padding
+    const OPTIONS_TEMPLATE_FLOWSET: [u8; 20] = [0x00, 0x01, 0x00, 0x14, 0x01, 0x01, 0x00, 0x04,
+                                                0x00, 0x04, 0x00, 0x01, 0x00, 0x04, 0x00, 0x22,
+                                                0x00, 0x04, 0x00, 0x00];
+
+    const DATA_FLOWSET: [u8; 40] = [0x01, 0x00, 0x00, 0x28, 0x00, 0x00, 0x05, 0xDC, 0xC0, 0x00,
+                                    0x02, 0x01, 0x00, 0x00, 0x03, 0xE8, 0x00, 0x00, 0x07, 0xD0,
+                                    0x00, 0x2A, 0x00, 0x00, 0x00, 0x40, 0xC0, 0x00, 0x02, 0x02,
+                                    0x00, 0x00, 0x0B, 0xB8, 0x00, 0x00, 0x0F, 0xA0, 0x00, 0x2B];
+
+    const OPTIONS_DATA_FLOWSET: [u8; 12] = [0x01, 0x01, 0x00, 0x0C, 0xC0, 0x00, 0x02, 0xFE,
+                                            0x00, 0x00, 0x00, 0x64];
+
+    // a NetFlow v5 packet from engine type 1, engine id 2
+    fn v5_packet(flow_sequence: u32, records: &[Vec<u8>]) -> Vec<u8> {
+        let count = records.len() as u16;
+        let mut bytes = vec![0x00, 0x05, (count >> 8) as u8, count as u8, 0x00, 0x00, 0x27, 0x10,
+                             0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&flow_sequence.to_be_bytes());
+        bytes.extend_from_slice(&[0x01, 0x02, 0x00, 0x00]);
+        for record in records {
+            bytes.extend_from_slice(record);
+        }
+        bytes
+    }
+
+    // TCP from 192.0.2.1:443 to 198.51.100.7:50000 through interfaces 3 and 5, AS 64496 to
+    // 64497, from 1 to 2 seconds of uptime
+    fn v5_record(octets: u32) -> Vec<u8> {
+        let mut bytes = vec![0xC0, 0x00, 0x02, 0x01, 0xC6, 0x33, 0x64, 0x07, 0x00, 0x00, 0x00,
+                             0x00, 0x00, 0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x0A];
+        bytes.extend_from_slice(&octets.to_be_bytes());
+        bytes.extend_from_slice(&[0x00, 0x00, 0x03, 0xE8, 0x00, 0x00, 0x07, 0xD0, 0x01, 0xBB,
+                                  0xC3, 0x50, 0x00, 0x1B, 0x06, 0x00, 0xFB, 0xF0, 0xFB, 0xF1,
+                                  0x18, 0x10, 0x00, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn test_netflow_v5() {
+        let mut parser = IpfixConsumer::new();
+        let mut bytes = v5_packet(100, &[v5_record(1500), v5_record(40)]);
+        // 1 in 100 packets, in sampling mode 1
+        bytes[22..24].copy_from_slice(&[0x40, 0x64]);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(parsed.header.version, 5);
+        assert_eq!(parsed.header.length, 120);
+        assert_eq!(parsed.header.export_time, 1479840970);
+        assert_eq!(parsed.header.sequence_number, 100);
+        assert_eq!(parsed.header.observation_domain_id, 0x0102);
+        assert_eq!(parsed.sys_uptime, Some(10000));
+        assert_eq!(parsed.sets.len(), 1);
+        assert_eq!(parsed.sets[0].template_id(), 0);
+
+        let records = parsed.sets[0].records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields().len(), 19);
+        assert_eq!(records[0].get(8),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 1)))));
+        assert_eq!(records[0].get(12),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(198, 51, 100, 7)))));
+        assert_eq!(records[0].get(10), Some(Ok(FieldValue::Unsigned32(3))));
+        assert_eq!(records[0].get(1), Some(Ok(FieldValue::Unsigned64(1500))));
+        assert_eq!(records[1].get(1), Some(Ok(FieldValue::Unsigned64(40))));
+        assert_eq!(records[0].get(7), Some(Ok(FieldValue::Unsigned16(443))));
+        assert_eq!(records[0].get(4), Some(Ok(FieldValue::Unsigned8(6))));
+        assert_eq!(records[0].get(16), Some(Ok(FieldValue::Unsigned32(64496))));
+        assert_eq!(records[0].get(13), Some(Ok(FieldValue::Unsigned8(16))));
+        assert_eq!(records[1].get(34), Some(Ok(FieldValue::Unsigned32(100))));
+        assert_eq!(parsed.uptime_to_time(2000),
+                   Some(UNIX_EPOCH + Duration::from_secs(1479840962)));
+
+        // the printer needs nothing more to print them
+        let json = IpfixPrinter::new().print_json(parser.parse_message(SESSION, &bytes)
+            .unwrap()
+            .sets
+            .remove(0));
+        assert!(json[0].contains(r#""sourceIPv4Address":"192.0.2.1""#));
+        assert!(json[0].contains(r#""bgpDestinationAsNumber":64497"#));
+        assert!(json[1].contains(r#""octetDeltaCount":40"#));
+        assert!(json[1].contains(r#""samplingInterval":100"#));
+    }
+
+    #[test]
+    fn test_netflow_v5_errors() {
+        // the flow sequence counts records
+        let mut parser = IpfixConsumer::new();
+        for &(flow_sequence, count, event) in [(0, 2, None),
+                                               (2, 1, None),
+                                               (5,
+                                                1,
+                                                Some(SequenceEvent::Gap {
+                                                    expected: 3,
+                                                    received: 5,
+                                                    missing: 2,
+                                                }))]
+            .iter() {
+            let bytes = v5_packet(flow_sequence, &vec![v5_record(1); count]);
+            assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sequence_event, event);
+        }
+
+        // the count in the header has to match the records in the packet
+        let mut bytes = v5_packet(0, &[v5_record(1), v5_record(2)]);
+        bytes.truncate(100);
+        assert_eq!(parser.parse_message(SESSION, &bytes).err(),
+                   Some(IpfixError::LengthMismatch {
+                       offset: 2,
+                       set_id: None,
+                       expected: 120,
+                       actual: 100,
+                   }));
+        assert!(parser.parse_message(SESSION, &bytes[..20]).is_err());
+
+        // and a length too long for a message header is refused rather than truncated
+        let bytes = v5_packet(0, &vec![v5_record(1); 1366]);
+        assert_eq!(parser.parse_message(SESSION, &bytes).err(),
+                   Some(IpfixError::InvalidMessageLength {
+                       offset: 2,
+                       set_id: None,
+                       length: 65592,
+                   }));
+        // even when the count in the header doesn't match the records either
+        let mut bytes = bytes;
+        bytes[2..4].copy_from_slice(&[0x00, 0x01]);
+        assert_eq!(parser.parse_message(SESSION, &bytes).err(),
+                   Some(IpfixError::InvalidMessageLength {
+                       offset: 2,
+                       set_id: None,
+                       length: 65592,
+                   }));
+    }
+
+    #[test]
+    fn test_netflow_v9() {
+        let mut parser = IpfixConsumer::new();
+        let mut flowsets = TEMPLATE_FLOWSET.to_vec();
+        flowsets.extend_from_slice(&OPTIONS_TEMPLATE_FLOWSET);
+        let bytes = packet(0, &flowsets);
+        assert!(parser.parse_message(SESSION, &bytes).unwrap().sets.is_empty());
+
+        let mut flowsets = DATA_FLOWSET.to_vec();
+        flowsets.extend_from_slice(&OPTIONS_DATA_FLOWSET);
+        let bytes = packet(1, &flowsets);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(parsed.header.version, 9);
+        assert_eq!(parsed.header.length, 72);
+        assert_eq!(parsed.header.export_time, 1479840970);
+        assert_eq!(parsed.header.sequence_number, 1);
+        assert_eq!(parsed.header.observation_domain_id, 1);
+        assert_eq!(parsed.sys_uptime, Some(10000));
+        assert_eq!(parsed.sequence_event, None);
+
+        let template_ids: Vec<u16> = parsed.sets.iter().map(|set| set.template_id()).collect();
+        assert_eq!(template_ids, vec![256, 257]);
+        let records = parsed.sets[0].records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get(1), Some(Ok(FieldValue::Unsigned64(1500))));
+        assert_eq!(records[0].get(8),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 1)))));
+        // vendor types are Cisco elements
+        assert_eq!(records[0].value(ElementId::enterprise(9, 234)), Some(&[0x00, 0x2A][..]));
+        assert_eq!(records[0].value(33002), None);
+        assert_eq!(records[1].get(1), Some(Ok(FieldValue::Unsigned64(64))));
+
+        // uptime timestamps
+        let start = match records[0].get(22) {
+            Some(Ok(FieldValue::Unsigned32(uptime))) => uptime,
+            value => panic!("unexpected flowStartSysUpTime {:?}", value),
+        };
+        assert_eq!(parsed.uptime_to_time(start),
+                   Some(UNIX_EPOCH + Duration::from_secs(1479840961)));
+        assert_eq!(parsed.uptime_to_time(11000),
+                   Some(UNIX_EPOCH + Duration::from_secs(1479840971)));
+
+        // the System scope is the exporter's address
+        let options = &parsed.sets[1].records()[0];
+        assert_eq!(options.get(130),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 254)))));
+        assert_eq!(options.get(34), Some(Ok(FieldValue::Unsigned32(100))));
+        let options = parsed.sets[1].options_records().unwrap();
+        assert_eq!(options[0].scope_value(130), Some(&[192, 0, 2, 254][..]));
+        assert_eq!(options[0].option_value(34), Some(&[0, 0, 0, 100][..]));
+    }
+
+    #[test]
+    fn test_netflow_v9_snapshot() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &packet(0, &TEMPLATE_FLOWSET)).unwrap();
+        let mut snapshot = Vec::new();
+        parser.export_templates(&mut snapshot).unwrap();
+
+        // the vendor field comes back as the same element
+        let mut parser = IpfixConsumer::new();
+        assert_eq!(parser.import_templates(&snapshot[..]).unwrap(), 1);
+        let bytes = packet(1, &DATA_FLOWSET);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &parsed.sets[0].records()[0];
+        assert_eq!(record.fields().len(), 5);
+        assert_eq!(record.value(ElementId::enterprise(9, 234)), Some(&[0x00, 0x2A][..]));
+        assert_eq!(record.get(1), Some(Ok(FieldValue::Unsigned64(1500))));
+    }
+
+    #[test]
+    fn test_netflow_v9_no_withdrawal() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &packet(0, &TEMPLATE_FLOWSET)).unwrap();
+        // template 257 in an IPFIX message from the same domain: interfaceName (variable)
+        let ipfix = [0x00, 0x0A, 0x00, 0x1C, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x0C, 0x01, 0x01, 0x00, 0x01, 0x00, 0x52,
+                     0xFF, 0xFF];
+        parser.parse_message(SESSION, &ipfix).unwrap();
+
+        // templates 2 and 256 without fields, which would withdraw everything in IPFIX
+        let flowset = [0x00, 0x00, 0x00, 0x0C, 0x00, 0x02, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00];
+        assert!(parser.parse_message(SESSION, &packet(1, &flowset)).unwrap().sets.is_empty());
+
+        let bytes = packet(2, &DATA_FLOWSET);
+        assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sets[0].records().len(), 2);
+        let ipfix = [0x00, 0x0A, 0x00, 0x1C, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x01, 0x01, 0x01, 0x00, 0x0C, 0x04, 0x65, 0x74, 0x68, 0x30, 0x00,
+                     0x00, 0x00];
+        assert_eq!(parser.parse_message(SESSION, &ipfix).unwrap().sets[0].records().len(), 1);
+    }
+
+    #[test]
+    fn test_netflow_v9_sequence_numbers() {
+        // sequence numbers count packets, not records
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &packet(7, &TEMPLATE_FLOWSET)).unwrap();
+        for &(sequence_number, event) in [(8, None),
+                                          (9, None),
+                                          (12,
+                                           Some(SequenceEvent::Gap {
+                                               expected: 10,
+                                               received: 12,
+                                               missing: 2,
+                                           }))]
+            .iter() {
+            let bytes = packet(sequence_number, &DATA_FLOWSET);
+            assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sequence_event, event);
+        }
+    }
+
+    #[test]
+    fn test_netflow_v9_and_ipfix() {
+        // one collector, one exporter switching from NetFlow v9 to IPFIX
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &packet(0, &TEMPLATE_FLOWSET)).unwrap();
+        let bytes = packet(1, &DATA_FLOWSET);
+        assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sets[0].records().len(), 2);
+
+        // template 256 in an IPFIX message: interfaceName (variable)
+        let mut ipfix = vec![0x00, 0x0A, 0x00, 0x30, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00,
+                             0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x0C, 0x01, 0x00,
+                             0x00, 0x01, 0x00, 0x52, 0xFF, 0xFF];
+        // and template flowsets, which IPFIX has no use for
+        ipfix.extend_from_slice(&[0x00, 0x00, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00]);
+        ipfix.extend_from_slice(&[0x01, 0x00, 0x00, 0x0C, 0x04, 0x65, 0x74, 0x68, 0x30, 0x00,
+                                  0x00, 0x00]);
+        let parsed = parser.parse_message(SESSION, &ipfix).unwrap();
+        assert_eq!(parsed.sys_uptime, None);
+        assert_eq!(parsed.uptime_to_time(0), None);
+        assert_eq!(parsed.sets[0].records()[0].get(82),
+                   Some(Ok(FieldValue::String(Cow::Borrowed("eth0")))));
+
+        // a packet too short for its header
+        let short = &packet(2, &[])[..19];
+        assert!(parser.parse_message(SESSION, short).is_err());
+    }
+}
@@ -4,7 +4,10 @@ mod tests {
 
     use std::io::prelude::*;
     use std::fs::File;
-    use self::ipfix::{IpfixConsumer, IpfixPrinter};
+    use self::ipfix::{ElementId, IpfixConsumer, IpfixPrinter};
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
 
     #[test]
     fn test_parse() {
@@ -120,11 +123,17 @@ mod tests {
         
         let printer = IpfixPrinter::new();
 
-        assert!(parser.parse_message(&*template).is_ok());
+        assert!(parser.parse_message(SESSION, &*template).is_ok());
         
-        if let Ok(datarecords) = parser.parse_message(&*data) {
+        if let Ok(message) = parser.parse_message(SESSION, &*data) {
+            assert_eq!(message.header.export_time, 0x583494CA);
+            assert_eq!(message.header.sequence_number, 0x08F36648);
+            assert_eq!(message.header.observation_domain_id, 0);
+            let template_ids: Vec<u16> = message.sets.iter().map(|set| set.template_id()).collect();
+            assert_eq!(template_ids, vec![999, 500, 999]);
+
             let mut test_string = String::new();
-            for datarecord in datarecords {
+            for datarecord in message.sets {
                 let flows = printer.print_json(datarecord);
                 for flow in flows {
                     test_string += &flow;
@@ -137,4 +146,132 @@ mod tests {
             assert_eq!(s, test_string);
         }
     }
+
+    #[test]
+    fn test_variable_length() {
+        // template 256: sourceIPv4Address (variable), destinationIPv6Address (variable),
+        // protocolIdentifier (1)
+        let template: [u8; 36] = [0x00, 0x0A, 0x00, 0x24, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00,
+                                  0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x14,
+                                  0x01, 0x00, 0x00, 0x03, 0x00, 0x08, 0xFF, 0xFF, 0x00, 0x1C,
+                                  0xFF, 0xFF, 0x00, 0x04, 0x00, 0x01];
+
+        // two records, the first with one byte length prefixes, the second with the three byte
+        // form for the IPv6 address
+        let data: [u8; 68] = [0x00, 0x0A, 0x00, 0x44, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x34, 0x04, 0x0A,
+                              0x00, 0x00, 0x01, 0x10, 0x20, 0x01, 0x0D, 0xB8, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x06, 0x04,
+                              0x0A, 0x00, 0x00, 0x02, 0xFF, 0x00, 0x10, 0x20, 0x01, 0x0D, 0xB8,
+                              0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                              0x02, 0x11];
+
+        let mut parser = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+
+        assert!(parser.parse_message(SESSION, &template).is_ok());
+        let mut message = parser.parse_message(SESSION, &data).unwrap();
+        assert_eq!(message.sets.len(), 1);
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"protocolIdentifier":6,"sourceIPv4Address":"10.0.0.1","destinationIPv6Address":"2001:0db8:0000:0000:0000:0000:0000:0001"}"#,
+                        r#"{"protocolIdentifier":17,"sourceIPv4Address":"10.0.0.2","destinationIPv6Address":"2001:0db8:0000:0000:0000:0000:0000:0002"}"#]);
+    }
+
+    #[test]
+    fn test_template_scope() {
+        use std::net::SocketAddr;
+
+        // template 256 in observation domain 1 as sourceIPv4Address (4), and the same template
+        // id as destinationIPv4Address (4)
+        let source_template: [u8; 28] = [0x00, 0x0A, 0x00, 0x1C, 0x58, 0x34, 0x94, 0xCA, 0x00,
+                                         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02,
+                                         0x00, 0x0C, 0x01, 0x00, 0x00, 0x01, 0x00, 0x08, 0x00,
+                                         0x04];
+        let mut destination_template = source_template;
+        destination_template[25] = 0x0C;
+
+        // one data record for template 256 in observation domain 1, and the same in domain 2
+        let data: [u8; 24] = [0x00, 0x0A, 0x00, 0x18, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x08, 0x0A, 0x00,
+                              0x00, 0x01];
+        let mut other_domain = data;
+        other_domain[15] = 0x02;
+
+        let first: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let second: SocketAddr = "192.0.2.2:4739".parse().unwrap();
+
+        let mut parser = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+
+        assert!(parser.parse_message(first, &source_template).is_ok());
+        assert!(parser.parse_message(second, &destination_template).is_ok());
+
+        let mut message = parser.parse_message(first, &data).unwrap();
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"sourceIPv4Address":"10.0.0.1"}"#]);
+        let mut message = parser.parse_message(second, &data).unwrap();
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"destinationIPv4Address":"10.0.0.1"}"#]);
+
+        // templates from domain 1 are not used for domain 2
+        assert!(parser.parse_message(first, &other_domain).err().unwrap().is_missing_template());
+    }
+
+    #[test]
+    fn test_enterprise_elements() {
+        // template 256: octetDeltaCount (4), then element 1 of enterprise 9 (4)
+        let template: [u8; 36] = [0x00, 0x0A, 0x00, 0x24, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00,
+                                  0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x14,
+                                  0x01, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x04, 0x80, 0x01,
+                                  0x00, 0x04, 0x00, 0x00, 0x00, 0x09];
+        let data: [u8; 28] = [0x00, 0x0A, 0x00, 0x1C, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0C, 0x00, 0x00,
+                              0x00, 0x64, 0x00, 0x00, 0x00, 0x07];
+
+        let mut parser = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+
+        assert!(parser.parse_message(SESSION, &template).is_ok());
+        let mut message = parser.parse_message(SESSION, &data).unwrap();
+        {
+            let record = &message.sets[0].records()[0];
+            assert_eq!(record.value(1), Some(&[0x00, 0x00, 0x00, 0x64][..]));
+            assert_eq!(record.value(ElementId::enterprise(9, 1)),
+                       Some(&[0x00, 0x00, 0x00, 0x07][..]));
+            assert_eq!(record.value(ElementId::enterprise(10, 1)), None);
+        }
+
+        // the vendor element is not printed as a second octetDeltaCount
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"octetDeltaCount":100}"#]);
+    }
+
+    #[test]
+    fn test_repeated_elements() {
+        // template 256: ingressInterface (4), protocolIdentifier (1), ingressInterface (4)
+        let template: [u8; 36] = [0x00, 0x0A, 0x00, 0x24, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00,
+                                  0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x14,
+                                  0x01, 0x00, 0x00, 0x03, 0x00, 0x0A, 0x00, 0x04, 0x00, 0x04,
+                                  0x00, 0x01, 0x00, 0x0A, 0x00, 0x04];
+        let data: [u8; 29] = [0x00, 0x0A, 0x00, 0x1D, 0x58, 0x34, 0x94, 0xCA, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0D, 0x00, 0x00,
+                              0x00, 0x02, 0x06, 0x00, 0x00, 0x00, 0x01];
+
+        let mut parser = IpfixConsumer::new();
+        let printer = IpfixPrinter::new();
+
+        assert!(parser.parse_message(SESSION, &template).is_ok());
+        let mut message = parser.parse_message(SESSION, &data).unwrap();
+        {
+            let record = &message.sets[0].records()[0];
+            assert_eq!(record.fields().len(), 3);
+            assert_eq!(record.fields()[1].0.element_id(), ElementId::iana(4));
+            assert_eq!(record.values(10),
+                       vec![&[0x00, 0x00, 0x00, 0x02][..], &[0x00, 0x00, 0x00, 0x01][..]]);
+            assert_eq!(record.value(10), Some(&[0x00, 0x00, 0x00, 0x02][..]));
+        }
+
+        assert_eq!(printer.print_json(message.sets.remove(0)),
+                   vec![r#"{"protocolIdentifier":6,"ingressInterface":[2,1]}"#]);
+    }
 }
@@ -0,0 +1,143 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use self::ipfix::{DataType, ElementId, FieldValue, IpfixConsumer, IpfixPrinter, ListSemantic,
+                      Registry, Semantics, StructuredList};
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    const VENDOR_ELEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<registry xmlns="http://www.iana.org/assignments" id="ipfix">
+  <registry id="ipfix-information-elements">
+    <record>
+      <name>ntopL7Proto</name>
+      <dataType>unsigned16</dataType>
+      <dataTypeSemantics>identifier</dataTypeSemantics>
+      <enterpriseId>35632</enterpriseId>
+      <elementId>118</elementId>
+    </record>
+    <record>
+      <name>vmwareTenantProtocol</name>
+      <dataType>unsigned8</dataType>
+      <enterpriseId>6876</enterpriseId>
+      <elementId>880</elementId>
+    </record>
+    <record>
+      <name>myOctetDeltaCount</name>
+      <dataType>unsigned64</dataType>
+      <elementId>1</elementId>
+      <units>octets</units>
+    </record>
+    <record>
+      <name>Reserved</name>
+      <elementId>2000-3000</elementId>
+    </record>
+  </registry>
+  <registry id="ipfix-version-numbers">
+    <record>
+      <value>10</value>
+      <name>IPFIX</name>
+    </record>
+  </registry>
+</registry>
+"#;
+
+    #[test]
+    fn test_load_xml() {
+        let mut registry = Registry::iana();
+        assert_eq!(registry.load_xml(VENDOR_ELEMENTS.as_bytes()).unwrap(), 3);
+
+        let ntop = registry.get(ElementId::enterprise(35632, 118)).unwrap();
+        assert_eq!(ntop.name, "ntopL7Proto");
+        assert_eq!(ntop.data_type, DataType::Unsigned16);
+        assert_eq!(ntop.semantics, Semantics::Identifier);
+        assert_eq!(registry.get(ElementId::enterprise(6876, 880)).unwrap().semantics,
+                   Semantics::Default);
+        // IANA elements can be redefined, and the rest are kept
+        assert_eq!(registry.get(1).unwrap().name, "myOctetDeltaCount");
+        assert_eq!(registry.get(2).unwrap().name, "packetDeltaCount");
+        assert_eq!(registry.get(118), None);
+    }
+
+    #[test]
+    fn test_bad_xml() {
+        let mut registry = Registry::new();
+        let unknown_type = VENDOR_ELEMENTS.replace("unsigned8", "unsigned7");
+        assert!(registry.load_xml(unknown_type.as_bytes()).is_err());
+        let bad_pen = VENDOR_ELEMENTS.replace("6876", "VMware");
+        assert!(registry.load_xml(bad_pen.as_bytes()).is_err());
+        assert!(registry.load_xml(&VENDOR_ELEMENTS.as_bytes()[..200]).is_err());
+        // nothing was added by the failed loads
+        assert_eq!(registry.get(ElementId::enterprise(35632, 118)), None);
+    }
+
+    #[test]
+    fn test_vendor_elements() {
+        // template 256: octetDeltaCount (8), ntopL7Proto (2), VMware element 880 (1), and Cisco
+        // element 12235 (4), which isn't defined anywhere
+        let template = [0x00, 0x02, 0x00, 0x24, 0x01, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x08,
+                        0x80, 0x76, 0x00, 0x02, 0x00, 0x00, 0x8B, 0x30, 0x83, 0x70, 0x00, 0x01,
+                        0x00, 0x00, 0x1A, 0xDC, 0xAF, 0xCB, 0x00, 0x04, 0x00, 0x00, 0x00, 0x09];
+        let data = [0x01, 0x00, 0x00, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0xDC,
+                    0x00, 0x07, 0x11, 0x00, 0x00, 0x00, 0x2A];
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let mut message = parser.parse_message(SESSION, &bytes).unwrap();
+
+        let mut registry = Registry::iana();
+        registry.load_xml(VENDOR_ELEMENTS.as_bytes()).unwrap();
+        let record = &message.sets[0].records()[0];
+        assert_eq!(record.get_with(&registry, ElementId::enterprise(35632, 118)),
+                   Some(Ok(FieldValue::Unsigned16(7))));
+
+        let printer = IpfixPrinter::with_registry(registry);
+        let expected = r#"{"myOctetDeltaCount":1500,"vmwareTenantProtocol":17,"ntopL7Proto":7}"#;
+        assert_eq!(printer.print_json(message.sets.remove(0)), vec![expected]);
+    }
+
+    #[test]
+    fn test_vendor_list() {
+        let elements = r#"<registry id="ipfix-information-elements">
+  <record>
+    <name>ntopProtocolList</name>
+    <dataType>basicList</dataType>
+    <enterpriseId>35632</enterpriseId>
+    <elementId>500</elementId>
+  </record>
+</registry>"#;
+        let mut registry = Registry::iana();
+        registry.load_xml(elements.as_bytes()).unwrap();
+
+        // template 256: ntop element 500 (variable)
+        let template = [0x00, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x01, 0x81, 0xF4, 0xFF, 0xFF,
+                        0x00, 0x00, 0x8B, 0x30];
+        // allOf protocolIdentifier 6 and 17
+        let data = [0x01, 0x00, 0x00, 0x0C, 0x07, 0x03, 0x00, 0x04, 0x00, 0x01, 0x06, 0x11];
+        let element = ElementId::enterprise(35632, 500);
+
+        // only a consumer with the registry knows the field holds a list
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        assert!(parsed.sets[0].records()[0].list(element).is_none());
+
+        let mut parser = IpfixConsumer::new().with_registry(registry);
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        match parsed.sets[0].records()[0].list(element) {
+            Some(&StructuredList::Basic { semantic, ref values, .. }) => {
+                assert_eq!(semantic, ListSemantic::AllOf);
+                assert_eq!(values.values(4), vec![&[6][..], &[17][..]]);
+            }
+            _ => panic!("basicList not decoded"),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use self::ipfix::{IpfixConsumer, SequenceEvent, SequenceStats};
+    use super::common::message_in;
+
+    const SESSION: u64 = 0;
+
+    // template 256: sourceIPv4Address (4)
+    const TEMPLATE_SET: [u8; 12] = [0x00, 0x02, 0x00, 0x0C, 0x01, 0x00, 0x00, 0x01, 0x00, 0x08,
+                                    0x00, 0x04];
+
+    // a data set for template 256 holding `records` records
+    fn data_set(records: u16) -> Vec<u8> {
+        let length = 4 + records * 4;
+        let mut bytes = vec![0x01, 0x00, (length >> 8) as u8, length as u8];
+        for i in 0..records {
+            bytes.extend_from_slice(&[0x0A, 0x00, 0x00, i as u8]);
+        }
+        bytes
+    }
+
+    fn event(parser: &mut IpfixConsumer,
+             sequence_number: u32,
+             records: u16)
+             -> Option<SequenceEvent> {
+        let bytes = message_in(1, sequence_number, &data_set(records));
+        parser.parse_message(SESSION, &bytes).unwrap().sequence_event
+    }
+
+    #[test]
+    fn test_sequence_tracking() {
+        let mut parser = IpfixConsumer::new();
+        assert_eq!(parser.sequence_stats(SESSION, 1), None);
+
+        // template sets don't carry records, so they don't move the sequence number on
+        let bytes = message_in(1, 100, &TEMPLATE_SET);
+        assert_eq!(parser.parse_message(SESSION, &bytes).unwrap().sequence_event, None);
+
+        assert_eq!(event(&mut parser, 100, 3), None);
+        assert_eq!(event(&mut parser, 103, 2), None);
+        assert_eq!(event(&mut parser, 110, 1),
+                   Some(SequenceEvent::Gap {
+                       expected: 105,
+                       received: 110,
+                       missing: 5,
+                   }));
+        assert_eq!(event(&mut parser, 105, 5),
+                   Some(SequenceEvent::Duplicate {
+                       expected: 111,
+                       received: 105,
+                   }));
+        assert_eq!(event(&mut parser, 111, 1), None);
+        assert_eq!(event(&mut parser, 0, 4),
+                   Some(SequenceEvent::Reset {
+                       expected: 112,
+                       received: 0,
+                   }));
+        assert_eq!(event(&mut parser, 4, 1), None);
+
+        assert_eq!(parser.sequence_stats(SESSION, 1),
+                   Some(SequenceStats {
+                       messages: 8,
+                       records: 17,
+                       lost_records: 5,
+                       gaps: 1,
+                       duplicates: 1,
+                       resets: 1,
+                   }));
+        assert_eq!(parser.sequence_stats(SESSION, 2), None);
+    }
+
+    #[test]
+    fn test_sequence_wraps() {
+        let mut parser = IpfixConsumer::new();
+        let bytes = message_in(1, 0xFFFFFFFE, &TEMPLATE_SET);
+        parser.parse_message(SESSION, &bytes).unwrap();
+
+        assert_eq!(event(&mut parser, 0xFFFFFFFE, 3), None);
+        assert_eq!(event(&mut parser, 1, 1), None);
+        assert_eq!(event(&mut parser, 4, 1),
+                   Some(SequenceEvent::Gap {
+                       expected: 2,
+                       received: 4,
+                       missing: 2,
+                   }));
+    }
+
+    #[test]
+    fn test_resync_after_unknown_template() {
+        let mut parser = IpfixConsumer::new();
+        let bytes = message_in(1, 0, &TEMPLATE_SET);
+        parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(event(&mut parser, 0, 2), None);
+
+        // a data set for a template we don't know: its records can't be counted
+        let mut unknown = data_set(3);
+        unknown[1] = 0x01;
+        let bytes = message_in(1, 2, &unknown);
+        assert!(parser.parse_message(SESSION, &bytes).is_err());
+
+        assert_eq!(event(&mut parser, 5, 1), None);
+    }
+}
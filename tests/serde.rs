@@ -0,0 +1,147 @@
+mod common;
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    extern crate ipfix;
+    extern crate serde_json;
+
+    use self::ipfix::{IpfixConsumer, IpfixPrinter, Registry};
+    use self::serde_json::json;
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    // template 256: octetDeltaCount (8), sourceIPv4Address (4), sourceMacAddress (6),
+    // interfaceName (variable), flowStartNanoseconds (8), Cisco element 12235 (2), the
+    // unassigned element 1000 (1), and octetDeltaCount again (4)
+    const TEMPLATE_SET: [u8; 44] = [0x00, 0x02, 0x00, 0x2C, 0x01, 0x00, 0x00, 0x08, 0x00, 0x01,
+                                    0x00, 0x08, 0x00, 0x08, 0x00, 0x04, 0x00, 0x38, 0x00, 0x06,
+                                    0x00, 0x52, 0xFF, 0xFF, 0x00, 0x9C, 0x00, 0x08, 0xAF, 0xCB,
+                                    0x00, 0x02, 0x00, 0x00, 0x00, 0x09, 0x03, 0xE8, 0x00, 0x01,
+                                    0x00, 0x01, 0x00, 0x04];
+
+    const DATA_SET: [u8; 42] = [0x01, 0x00, 0x00, 0x2A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                0x05, 0xDC, 0xC0, 0x00, 0x02, 0x01, 0x00, 0x1B, 0x21, 0x01,
+                                0x02, 0x03, 0x04, 0x65, 0x74, 0x68, 0x30, 0xDD, 0x12, 0xAD,
+                                0x80, 0x80, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x00, 0x00,
+                                0x00, 0x2A];
+
+    #[test]
+    fn test_serialize_message() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&DATA_SET);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+
+        assert_eq!(serde_json::to_value(&message).unwrap(),
+                   json!({
+                       "header": {
+                           "version": 10,
+                           "length": 58,
+                           "export_time": 1479840970,
+                           "sequence_number": 0,
+                           "observation_domain_id": 0,
+                       },
+                       "sets": [{
+                           "template_id": 256,
+                           "records": [{
+                               "octetDeltaCount": [1500, 42],
+                               "sourceIPv4Address": "192.0.2.1",
+                               "sourceMacAddress": "00:1b:21:01:02:03",
+                               "interfaceName": "eth0",
+                               "flowStartNanoseconds": 1500000000500000000u64,
+                               "9.12235": [1, 2],
+                               "0.1000": [3],
+                           }],
+                       }],
+                       "recovered": [],
+                       "sequence_event": null,
+                   }));
+    }
+
+    #[test]
+    fn test_serialize_invalid_value() {
+        // template 256: sourceIPv4Address (3), dataRecordsReliability (1)
+        let template = [0x00, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x02, 0x00, 0x08, 0x00, 0x03,
+                        0x01, 0x14, 0x00, 0x01];
+        let data = [0x01, 0x00, 0x00, 0x08, 0x0A, 0x00, 0x00, 0x02];
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(serde_json::to_value(&message.sets[0].records()[0]).unwrap(),
+                   json!({ "sourceIPv4Address": null, "dataRecordsReliability": false }));
+    }
+
+    #[test]
+    fn test_serialize_lists() {
+        // template 256: basicList, subTemplateList; template 257: sourceTransportPort (2)
+        let template = [0x00, 0x02, 0x00, 0x18, 0x01, 0x00, 0x00, 0x02, 0x01, 0x23, 0xFF, 0xFF,
+                        0x01, 0x24, 0xFF, 0xFF, 0x01, 0x01, 0x00, 0x01, 0x00, 0x07, 0x00, 0x02];
+        // noneOf protocolIdentifier 6 and 17, and an unassigned semantic over ports 80 and 443
+        let data = [0x01, 0x00, 0x00, 0x16, 0x07, 0x00, 0x00, 0x04, 0x00, 0x01, 0x06, 0x11,
+                    0x08, 0x07, 0x01, 0x01, 0x00, 0x50, 0x01, 0xBB, 0x00, 0x00];
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(serde_json::to_value(&message.sets[0].records()[0]).unwrap(),
+                   json!({
+                       "basicList": { "semantic": "noneOf", "protocolIdentifier": [6, 17] },
+                       "subTemplateList": {
+                           "semantic": 7,
+                           "template_id": 257,
+                           "records": [{ "sourceTransportPort": 80 },
+                                       { "sourceTransportPort": 443 }],
+                       },
+                   }));
+    }
+
+    #[test]
+    fn test_serialize_with_registry() {
+        let cisco = r#"<registry id="ipfix-information-elements">
+  <record>
+    <name>ciscoApplicationId</name>
+    <dataType>unsigned16</dataType>
+    <enterpriseId>9</enterpriseId>
+    <elementId>12235</elementId>
+  </record>
+</registry>"#;
+        let mut registry = Registry::iana();
+        registry.load_xml(cisco.as_bytes()).unwrap();
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&DATA_SET);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &message.sets[0].records()[0];
+        let value = serde_json::to_value(record.with_registry(&registry)).unwrap();
+        assert_eq!(value["ciscoApplicationId"], json!(258));
+        assert_eq!(value.get("9.12235"), None);
+        let value = serde_json::to_value(message.with_registry(&registry)).unwrap();
+        assert_eq!(value["sets"][0]["records"][0]["ciscoApplicationId"], json!(258));
+    }
+
+    #[test]
+    fn test_serialize_order() {
+        // template 256: sourceIPv4Address (4), protocolIdentifier (1), sourceTransportPort (2)
+        let template = [0x00, 0x02, 0x00, 0x14, 0x01, 0x00, 0x00, 0x03, 0x00, 0x08, 0x00, 0x04,
+                        0x00, 0x04, 0x00, 0x01, 0x00, 0x07, 0x00, 0x02];
+        let data = [0x01, 0x00, 0x00, 0x0B, 0x0A, 0x00, 0x00, 0x02, 0x06, 0x00, 0x50];
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        // elements come in the order the printer prints them in, by element id
+        let serialized = serde_json::to_string(&message.sets[0].records()[0]).unwrap();
+        assert_eq!(serialized,
+                   concat!(r#"{"protocolIdentifier":6,"sourceTransportPort":80,"#,
+                           r#""sourceIPv4Address":"10.0.0.2"}"#));
+        let printed = IpfixPrinter::new().print_json(message.sets.into_iter().next().unwrap());
+        assert_eq!(printed, vec![serialized]);
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use self::ipfix::{FieldValue, IpfixPrinter, SequenceEvent, SflowDecoder, SflowError};
+    use self::ipfix::sflow::InterfaceCounters;
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes().to_vec()).collect()
+    }
+
+    // a structure with its format and length in front of it
+    fn tagged(format: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = words(&[format, data.len() as u32]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    // a datagram from agent 192.0.2.1, 10 seconds after it booted
+    fn datagram(sequence_number: u32, samples: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = words(&[5, 1, 0xC000_0201, 0, sequence_number, 10000,
+                                samples.len() as u32]);
+        for sample in samples {
+            bytes.extend_from_slice(sample);
+        }
+        bytes
+    }
+
+    fn raw_header(header_protocol: u32, header: &[u8]) -> Vec<u8> {
+        let mut data = words(&[header_protocol, 1514, 4, header.len() as u32]);
+        data.extend_from_slice(header);
+        while !data.len().is_multiple_of(4) {
+            data.push(0);
+        }
+        tagged(1, &data)
+    }
+
+    // 1 in 1000 packets, from interface 3 to 5
+    fn flow_sample(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = words(&[1, 3, 1000, 5000, 0, 3, 5, records.len() as u32]);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        tagged(1, &data)
+    }
+
+    // TCP from 192.0.2.1:443 to 198.51.100.7:50000 with PSH and ACK, on VLAN 100
+    const ETHERNET_HEADER: [u8; 52] =
+        [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0x81, 0x00,
+         0x00, 0x64, 0x08, 0x00, 0x45, 0x00, 0x05, 0xDC, 0x00, 0x00, 0x40, 0x00, 0x40, 0x06,
+         0x00, 0x00, 0xC0, 0x00, 0x02, 0x01, 0xC6, 0x33, 0x64, 0x07, 0x01, 0xBB, 0xC3, 0x50,
+         0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x50, 0x18];
+
+    // UDP from [2001:db8::1]:53 to [2001:db8::2]:5353, with flow label 1
+    const IPV6_HEADER: [u8; 44] =
+        [0x60, 0x00, 0x00, 0x01, 0x00, 0x10, 0x11, 0x40, 0x20, 0x01, 0x0D, 0xB8, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x20, 0x01, 0x0D, 0xB8,
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x35,
+         0x14, 0xE9];
+
+    #[test]
+    fn test_flow_sample() {
+        let mut decoder = SflowDecoder::new();
+        let bytes = datagram(1, &[flow_sample(&[raw_header(1, &ETHERNET_HEADER)])]);
+        let parsed = decoder.parse_datagram(&bytes).unwrap();
+        assert_eq!(parsed.agent_address, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        assert_eq!(parsed.sequence_number, 1);
+        assert_eq!(parsed.uptime, 10000);
+        assert!(parsed.counters.is_empty());
+        assert_eq!(parsed.flows.template_id(), 0);
+
+        let records = parsed.flows.records();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.get(34), Some(Ok(FieldValue::Unsigned32(1000))));
+        assert_eq!(record.get(10), Some(Ok(FieldValue::Unsigned32(3))));
+        assert_eq!(record.get(14), Some(Ok(FieldValue::Unsigned32(5))));
+        assert_eq!(record.get(312), Some(Ok(FieldValue::Unsigned16(1514))));
+        assert_eq!(record.get(56),
+                   Some(Ok(FieldValue::MacAddress([0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB]))));
+        assert_eq!(record.get(58), Some(Ok(FieldValue::Unsigned16(100))));
+        assert_eq!(record.get(256), Some(Ok(FieldValue::Unsigned16(0x0800))));
+        assert_eq!(record.get(224), Some(Ok(FieldValue::Unsigned64(1500))));
+        assert_eq!(record.get(8),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 1)))));
+        assert_eq!(record.get(12),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(198, 51, 100, 7)))));
+        assert_eq!(record.get(4), Some(Ok(FieldValue::Unsigned8(6))));
+        assert_eq!(record.get(7), Some(Ok(FieldValue::Unsigned16(443))));
+        assert_eq!(record.get(11), Some(Ok(FieldValue::Unsigned16(50000))));
+        assert_eq!(record.get(6), Some(Ok(FieldValue::Unsigned16(0x18))));
+
+        // a header cut short keeps the fields it has
+        let bytes = datagram(2, &[flow_sample(&[raw_header(1, &ETHERNET_HEADER[..40])])]);
+        let parsed = decoder.parse_datagram(&bytes).unwrap();
+        let record = &parsed.flows.records()[0];
+        assert!(record.value(12).is_some());
+        assert!(record.value(7).is_none());
+
+        let json = IpfixPrinter::new().print_json(parsed.flows);
+        assert!(json[0].contains(r#""samplingInterval":1000"#));
+        assert!(json[0].contains(r#""sourceIPv4Address":"192.0.2.1""#));
+        assert!(json[0].contains(r#""vlanId":100"#));
+    }
+
+    #[test]
+    fn test_expanded_flow_sample() {
+        // 1 in 512 packets, from interface 7 out of several interfaces, with a record of an
+        // unknown format before the header
+        let mut data = words(&[1, 0, 7, 512, 1024, 0, 0, 7, 2, 2, 2]);
+        data.extend(tagged(1001, &[0, 0, 0, 1]));
+        data.extend(raw_header(12, &IPV6_HEADER));
+        let bytes = datagram(1, &[tagged(3, &data), tagged(5, &[0; 8])]);
+
+        let parsed = SflowDecoder::new().parse_datagram(&bytes).unwrap();
+        let records = parsed.flows.records();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.get(34), Some(Ok(FieldValue::Unsigned32(512))));
+        assert_eq!(record.get(10), Some(Ok(FieldValue::Unsigned32(7))));
+        assert!(record.value(14).is_none());
+        assert!(record.value(56).is_none());
+        assert_eq!(record.get(27),
+                   Some(Ok(FieldValue::Ipv6Address(Ipv6Addr::new(0x2001, 0xDB8, 0, 0, 0, 0, 0,
+                                                                 1)))));
+        assert_eq!(record.get(28),
+                   Some(Ok(FieldValue::Ipv6Address(Ipv6Addr::new(0x2001, 0xDB8, 0, 0, 0, 0, 0,
+                                                                 2)))));
+        assert_eq!(record.get(31), Some(Ok(FieldValue::Unsigned32(1))));
+        assert_eq!(record.get(224), Some(Ok(FieldValue::Unsigned64(56))));
+        assert_eq!(record.get(4), Some(Ok(FieldValue::Unsigned8(17))));
+        assert_eq!(record.get(7), Some(Ok(FieldValue::Unsigned16(53))));
+        assert_eq!(record.get(11), Some(Ok(FieldValue::Unsigned16(5353))));
+    }
+
+    #[test]
+    fn test_counter_sample() {
+        let counters = words(&[3, 6, 0, 1_000_000_000, 1, 3, 0, 12345, 10, 2, 1, 0, 0, 0, 0,
+                               67890, 20, 4, 3, 0, 0, 0]);
+        let mut data = words(&[9, 3, 1]);
+        data.extend(tagged(1, &counters));
+        let expanded = {
+            let mut data = words(&[10, 0, 4, 1]);
+            data.extend(tagged(2, &[0; 8]));
+            data
+        };
+        let bytes = datagram(1, &[tagged(2, &data), tagged(4, &expanded)]);
+
+        let parsed = SflowDecoder::new().parse_datagram(&bytes).unwrap();
+        assert!(parsed.flows.records().is_empty());
+        assert_eq!(parsed.counters.len(), 2);
+        assert_eq!(parsed.counters[0].sequence_number, 9);
+        assert_eq!(parsed.counters[0].source_id_index, 3);
+        assert_eq!(parsed.counters[0].interface,
+                   Some(InterfaceCounters {
+                       if_index: 3,
+                       if_type: 6,
+                       if_speed: 1_000_000_000,
+                       if_direction: 1,
+                       if_status: 3,
+                       if_in_octets: 12345,
+                       if_in_ucast_pkts: 10,
+                       if_in_multicast_pkts: 2,
+                       if_in_broadcast_pkts: 1,
+                       if_out_octets: 67890,
+                       if_out_ucast_pkts: 20,
+                       if_out_multicast_pkts: 4,
+                       if_out_broadcast_pkts: 3,
+                       ..InterfaceCounters::default()
+                   }));
+        assert_eq!(parsed.counters[1].source_id_index, 4);
+        assert_eq!(parsed.counters[1].interface, None);
+    }
+
+    #[test]
+    fn test_sflow_errors() {
+        let mut decoder = SflowDecoder::new();
+        let mut bytes = datagram(1, &[]);
+        bytes[3] = 4;
+        assert_eq!(decoder.parse_datagram(&bytes).err(), Some(SflowError::BadVersion(4)));
+        bytes[3] = 5;
+        bytes[7] = 3;
+        assert_eq!(decoder.parse_datagram(&bytes).err(),
+                   Some(SflowError::UnknownAddressType(3)));
+
+        // a sample longer than the datagram
+        let bytes = datagram(1, &[flow_sample(&[raw_header(1, &ETHERNET_HEADER)])]);
+        assert_eq!(decoder.parse_datagram(&bytes[..bytes.len() - 4]).err(),
+                   Some(SflowError::Truncated { offset: 36 }));
+        assert_eq!(decoder.parse_datagram(&bytes[..20]).err(),
+                   Some(SflowError::Truncated { offset: 20 }));
+        assert!(decoder.sequence_stats(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 0).is_none());
+
+        // datagrams are numbered one by one
+        decoder.parse_datagram(&datagram(1, &[])).unwrap();
+        decoder.parse_datagram(&datagram(2, &[])).unwrap();
+        let parsed = decoder.parse_datagram(&datagram(5, &[])).unwrap();
+        assert_eq!(parsed.sequence_event,
+                   Some(SequenceEvent::Gap {
+                       expected: 3,
+                       received: 5,
+                       missing: 2,
+                   }));
+        let stats = decoder.sequence_stats(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 0).unwrap();
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.lost_records, 2);
+    }
+}
@@ -0,0 +1,153 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::thread;
+    use std::time::Duration;
+    use self::ipfix::{IpfixConsumer, IpfixError, StreamDecoder};
+    use super::common::message;
+
+    // one TCP connection
+    const SESSION: u64 = 0;
+
+    // template 256: sourceIPv4Address (4)
+    const TEMPLATE_SET: [u8; 12] = [0x00, 0x02, 0x00, 0x0C, 0x01, 0x00, 0x00, 0x01, 0x00, 0x08,
+                                    0x00, 0x04];
+
+    // template 256 again: destinationIPv4Address (4)
+    const OTHER_TEMPLATE_SET: [u8; 12] = [0x00, 0x02, 0x00, 0x0C, 0x01, 0x00, 0x00, 0x01, 0x00,
+                                          0x0C, 0x00, 0x04];
+
+    // two records for template 256
+    const DATA_SET: [u8; 12] = [0x01, 0x00, 0x00, 0x0C, 0x0A, 0x00, 0x00, 0x01, 0x0A, 0x00, 0x00,
+                                0x02];
+
+    fn stream() -> Vec<u8> {
+        let mut bytes = message(&TEMPLATE_SET);
+        bytes.extend(message(&DATA_SET));
+        bytes.extend(message(&[]));
+        bytes.extend(message(&DATA_SET));
+        bytes
+    }
+
+    #[test]
+    fn test_framing() {
+        let bytes = stream();
+        for chunk_size in 1..bytes.len() + 1 {
+            let mut consumer = IpfixConsumer::new();
+            let mut decoder = StreamDecoder::new(SESSION);
+            let mut records = Vec::<usize>::new();
+            for chunk in bytes.chunks(chunk_size) {
+                decoder.push(chunk);
+                while let Some(message) = decoder.next_message(&mut consumer) {
+                    let message = message.unwrap();
+                    records.push(message.sets.iter().map(|set| set.records().len()).sum());
+                }
+            }
+            assert_eq!(records, vec![0, 2, 0, 2]);
+            assert_eq!(decoder.buffered(), 0);
+        }
+
+        // a message cut short stays in the buffer
+        let mut decoder = StreamDecoder::new(SESSION);
+        decoder.push(&bytes[..bytes.len() - 1]);
+        assert_eq!(decoder.next_frame().unwrap().map(|frame| frame.len()), Some(28));
+        assert_eq!(decoder.next_frame().unwrap().map(|frame| frame.len()), Some(28));
+        assert_eq!(decoder.next_frame().unwrap().map(|frame| frame.len()), Some(16));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        assert_eq!(decoder.buffered(), 27);
+    }
+
+    #[test]
+    fn test_framing_errors() {
+        let mut bytes = message(&TEMPLATE_SET);
+        bytes.extend(message(&DATA_SET));
+
+        // the error is known from the first 4 bytes of a header
+        let mut short = bytes.clone();
+        short[30..32].copy_from_slice(&[0x00, 0x0F]);
+        let mut decoder = StreamDecoder::new(SESSION);
+        decoder.push(&short[..32]);
+        assert_eq!(decoder.next_frame().unwrap().map(|frame| frame.len()), Some(28));
+        assert_eq!(decoder.next_frame(),
+                   Err(IpfixError::InvalidMessageLength {
+                       offset: 2,
+                       set_id: None,
+                       length: 15,
+                   }));
+        assert!(decoder.is_failed());
+        decoder.push(&short[32..]);
+        assert_eq!(decoder.next_frame(), Ok(None));
+        assert_eq!(decoder.buffered(), 0);
+
+        let mut decoder = StreamDecoder::new(SESSION).with_max_message_length(27);
+        decoder.push(&bytes);
+        assert_eq!(decoder.next_frame(),
+                   Err(IpfixError::InvalidMessageLength {
+                       offset: 2,
+                       set_id: None,
+                       length: 28,
+                   }));
+
+        let mut netflow = bytes.clone();
+        netflow[1] = 0x09;
+        let mut consumer = IpfixConsumer::new();
+        let mut decoder = StreamDecoder::new(SESSION);
+        decoder.push(&netflow);
+        assert_eq!(decoder.next_message(&mut consumer).unwrap().err(),
+                   Some(IpfixError::BadVersion {
+                       offset: 0,
+                       set_id: None,
+                       version: 9,
+                   }));
+        assert!(decoder.next_message(&mut consumer).is_none());
+    }
+
+    #[test]
+    fn test_stream_templates() {
+        let mut consumer = IpfixConsumer::new().with_template_timeout(Duration::from_millis(10));
+        let mut decoder = StreamDecoder::new(SESSION);
+        decoder.push(&message(&TEMPLATE_SET));
+        decoder.next_message(&mut consumer).unwrap().unwrap();
+
+        // templates on a stream session don't time out
+        thread::sleep(Duration::from_millis(50));
+        consumer.expire_templates();
+        decoder.push(&message(&DATA_SET));
+        let parsed = decoder.next_message(&mut consumer).unwrap().unwrap();
+        assert_eq!(parsed.sets[0].records().len(), 2);
+
+        // resending a template unchanged is fine, changing it without a withdrawal is not
+        decoder.push(&message(&TEMPLATE_SET));
+        decoder.next_message(&mut consumer).unwrap().unwrap();
+        decoder.push(&message(&OTHER_TEMPLATE_SET));
+        assert_eq!(decoder.next_message(&mut consumer).unwrap().err(),
+                   Some(IpfixError::TemplateRedefined {
+                       offset: 16,
+                       set_id: Some(2),
+                       template_id: 256,
+                   }));
+        assert!(!decoder.is_failed());
+
+        let mut sets = vec![0x00, 0x02, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00];
+        sets.extend_from_slice(&OTHER_TEMPLATE_SET);
+        decoder.push(&message(&sets));
+        decoder.next_message(&mut consumer).unwrap().unwrap();
+
+        // a closed session takes its templates with it
+        consumer.close_session(SESSION);
+        decoder.push(&message(&DATA_SET));
+        let error = decoder.next_message(&mut consumer).unwrap().err().unwrap();
+        assert!(error.is_missing_template());
+
+        // UDP sessions can redefine templates, which expire
+        let udp = 1u64;
+        consumer.parse_message(udp, &message(&TEMPLATE_SET)).unwrap();
+        consumer.parse_message(udp, &message(&OTHER_TEMPLATE_SET)).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let error = consumer.parse_message(udp, &message(&DATA_SET)).err().unwrap();
+        assert!(error.is_missing_template());
+    }
+}
@@ -0,0 +1,243 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::thread;
+    use std::time::Duration;
+    use self::ipfix::{IpfixConsumer, IpfixError, TemplateKind};
+    use super::common::message;
+
+    const SESSION: u64 = 0;
+
+    // templates 256 and 257: sourceIPv4Address (4)
+    const TEMPLATE_SET: [u8; 20] = [0x00, 0x02, 0x00, 0x14, 0x01, 0x00, 0x00, 0x01, 0x00, 0x08,
+                                    0x00, 0x04, 0x01, 0x01, 0x00, 0x01, 0x00, 0x08, 0x00, 0x04];
+
+    // options template 258: scope exportingProcessId (4), option exportedMessageTotalCount (8)
+    const OPTIONS_TEMPLATE_SET: [u8; 18] = [0x00, 0x03, 0x00, 0x12, 0x01, 0x02, 0x00, 0x02,
+                                            0x00, 0x01, 0x00, 0x90, 0x00, 0x04, 0x00, 0x29,
+                                            0x00, 0x08];
+
+    fn decodes(parser: &mut IpfixConsumer, template_id: u16) -> bool {
+        let bytes = match template_id {
+            258 => {
+                message(&[0x01, 0x02, 0x00, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+                          0x00, 0x00, 0x00, 0x00, 0x05])
+            }
+            _ => {
+                message(&[(template_id >> 8) as u8, template_id as u8, 0x00, 0x08, 0x0A, 0x00,
+                          0x00, 0x01])
+            }
+        };
+        match parser.parse_message(SESSION, &bytes) {
+            Ok(message) => message.sets[0].records().len() == 1,
+            Err(err) => {
+                assert!(err.is_missing_template());
+                false
+            }
+        }
+    }
+
+    fn setup() -> IpfixConsumer {
+        let mut parser = IpfixConsumer::new();
+        let mut sets = TEMPLATE_SET.to_vec();
+        sets.extend_from_slice(&OPTIONS_TEMPLATE_SET);
+        parser.parse_message(SESSION, &message(&sets)).unwrap();
+        assert!(decodes(&mut parser, 256));
+        assert!(decodes(&mut parser, 257));
+        assert!(decodes(&mut parser, 258));
+        parser
+    }
+
+    #[test]
+    fn test_template_withdrawal() {
+        let mut parser = setup();
+        let withdrawal = message(&[0x00, 0x02, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00]);
+        parser.parse_message(SESSION, &withdrawal).unwrap();
+        assert!(!decodes(&mut parser, 256));
+        assert!(decodes(&mut parser, 257));
+        assert!(decodes(&mut parser, 258));
+    }
+
+    #[test]
+    fn test_all_templates_withdrawal() {
+        let mut parser = setup();
+        let withdrawal = message(&[0x00, 0x02, 0x00, 0x08, 0x00, 0x02, 0x00, 0x00]);
+        parser.parse_message(SESSION, &withdrawal).unwrap();
+        assert!(!decodes(&mut parser, 256));
+        assert!(!decodes(&mut parser, 257));
+        assert!(decodes(&mut parser, 258));
+    }
+
+    #[test]
+    fn test_options_template_withdrawal() {
+        // withdrawal records in an options template set carry no scope field count
+        let mut parser = setup();
+        let withdrawal = message(&[0x00, 0x03, 0x00, 0x08, 0x01, 0x02, 0x00, 0x00]);
+        parser.parse_message(SESSION, &withdrawal).unwrap();
+        assert!(decodes(&mut parser, 256));
+        assert!(!decodes(&mut parser, 258));
+
+        let mut parser = setup();
+        let withdrawal = message(&[0x00, 0x03, 0x00, 0x08, 0x00, 0x03, 0x00, 0x00]);
+        parser.parse_message(SESSION, &withdrawal).unwrap();
+        assert!(decodes(&mut parser, 257));
+        assert!(!decodes(&mut parser, 258));
+    }
+
+    #[test]
+    fn test_options_template_without_scope() {
+        let mut set = OPTIONS_TEMPLATE_SET.to_vec();
+        set[9] = 0x00;
+        let mut parser = IpfixConsumer::new();
+        assert_eq!(parser.parse_message(SESSION, &message(&set)).err(),
+                   Some(IpfixError::MalformedFieldSpecifier {
+                       offset: 24,
+                       set_id: Some(3),
+                   }));
+        assert!(!decodes(&mut parser, 258));
+    }
+
+    #[test]
+    fn test_options_records() {
+        let mut parser = setup();
+        let bytes = message(&[0x01, 0x02, 0x00, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+                              0x00, 0x00, 0x00, 0x00, 0x05]);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        let set = &parsed.sets[0];
+        assert!(set.is_options());
+        assert_eq!(set.template_kind(),
+                   TemplateKind::OptionsTemplate { scope_field_count: 1 });
+
+        let records = set.options_records().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = records[0];
+        assert_eq!(record.scope_fields().len(), 1);
+        assert_eq!(record.scope_fields()[0].0.element_id().id, 144);
+        assert_eq!(record.scope_value(144), Some(&[0x00, 0x00, 0x00, 0x01][..]));
+        assert_eq!(record.option_fields().len(), 1);
+        assert_eq!(record.option_value(41), Some(&[0, 0, 0, 0, 0, 0, 0, 5][..]));
+        // scope and option fields are told apart
+        assert_eq!(record.option_value(144), None);
+        assert_eq!(record.scope_value(41), None);
+        assert_eq!(record.record().fields().len(), 2);
+
+        // a set of a template has no scope
+        let bytes = message(&[0x01, 0x00, 0x00, 0x08, 0x0A, 0x00, 0x00, 0x01]);
+        let parsed = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(parsed.sets[0].template_kind(), TemplateKind::Template);
+        assert!(!parsed.sets[0].is_options());
+        assert!(parsed.sets[0].options_records().is_none());
+    }
+
+    #[test]
+    fn test_template_timeout() {
+        let mut parser = IpfixConsumer::new().with_template_timeout(Duration::from_millis(200));
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        assert!(decodes(&mut parser, 256));
+
+        thread::sleep(Duration::from_millis(300));
+        assert!(!decodes(&mut parser, 256));
+
+        // templates are good again once the exporter refreshes them
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        assert!(decodes(&mut parser, 256));
+        parser.expire_templates();
+        assert!(decodes(&mut parser, 257));
+    }
+
+    #[test]
+    fn test_pending_queue() {
+        let mut parser = IpfixConsumer::new().with_pending_queue(16, Duration::from_secs(60));
+
+        // two records for template 256, then one that doesn't fit in the queue any more
+        let data = message(&[0x01, 0x00, 0x00, 0x0C, 0x0A, 0x00, 0x00, 0x01, 0x0A, 0x00, 0x00,
+                             0x02]);
+        let parsed = parser.parse_message(SESSION, &data).unwrap();
+        assert!(parsed.sets.is_empty());
+        assert_eq!(parser.pending_bytes(), 8);
+        let overflow = message(&[0x01, 0x01, 0x00, 0x10, 0x0A, 0x00, 0x00, 0x03, 0x0A, 0x00,
+                                 0x00, 0x04, 0x0A, 0x00, 0x00, 0x05]);
+        assert!(parser.parse_message(SESSION, &overflow).err().unwrap().is_missing_template());
+
+        let templates = message(&TEMPLATE_SET);
+        let parsed = parser.parse_message(SESSION, &templates).unwrap();
+        assert_eq!(parser.pending_bytes(), 0);
+        assert_eq!(parsed.recovered.len(), 1);
+        let (ref header, ref set) = parsed.recovered[0];
+        assert_eq!(header.length, 28);
+        assert_eq!(set.template_id(), 256);
+        assert_eq!(set.records().len(), 2);
+    }
+
+    #[test]
+    fn test_pending_queue_expiry() {
+        let mut parser = IpfixConsumer::new().with_pending_queue(1024, Duration::from_millis(100));
+        let data = message(&[0x01, 0x00, 0x00, 0x08, 0x0A, 0x00, 0x00, 0x01]);
+        parser.parse_message(SESSION, &data).unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let templates = message(&TEMPLATE_SET);
+        assert!(parser.parse_message(SESSION, &templates).unwrap().recovered.is_empty());
+        assert_eq!(parser.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn test_pending_bytes_expiry() {
+        // plenty of room left, so nothing forces the queue to drop expired sets
+        let mut parser = IpfixConsumer::new().with_pending_queue(1024, Duration::from_millis(100));
+        let data = message(&[0x01, 0x00, 0x00, 0x08, 0x0A, 0x00, 0x00, 0x01]);
+        parser.parse_message(SESSION, &data).unwrap();
+        assert_eq!(parser.pending_bytes(), 4);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(parser.pending_bytes(), 0);
+
+        // and the sets pushed after it are counted on their own
+        parser.parse_message(SESSION, &data).unwrap();
+        assert_eq!(parser.pending_bytes(), 4);
+    }
+
+    #[test]
+    fn test_template_snapshot() {
+        let mut snapshot = Vec::new();
+        setup().export_templates(&mut snapshot).unwrap();
+
+        let mut parser = IpfixConsumer::new();
+        assert_eq!(parser.import_templates(&snapshot[..]).unwrap(), 3);
+        assert!(decodes(&mut parser, 256));
+        assert!(decodes(&mut parser, 257));
+        assert!(decodes(&mut parser, 258));
+
+        // templates from other sessions stay out of reach
+        let bytes = message(&[0x01, 0x00, 0x00, 0x08, 0x0A, 0x00, 0x00, 0x01]);
+        assert!(parser.parse_message(1u64, &bytes).err().unwrap().is_missing_template());
+    }
+
+    #[test]
+    fn test_bad_template_snapshot() {
+        let mut snapshot = Vec::new();
+        setup().export_templates(&mut snapshot).unwrap();
+
+        let mut parser = IpfixConsumer::new();
+        assert!(parser.import_templates(&snapshot[..snapshot.len() - 1]).is_err());
+        let mut version = snapshot.clone();
+        version[5] = 0x02;
+        assert!(parser.import_templates(&version[..]).is_err());
+        assert!(parser.import_templates(&b"IPFIX"[..]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_template_snapshot() {
+        let mut snapshot = Vec::new();
+        setup().export_templates(&mut snapshot).unwrap();
+
+        // the templates before the truncated one aren't loaded either
+        let mut parser = IpfixConsumer::new();
+        assert!(parser.import_templates(&snapshot[..snapshot.len() - 1]).is_err());
+        assert!(!decodes(&mut parser, 256));
+        assert!(!decodes(&mut parser, 257));
+        assert!(!decodes(&mut parser, 258));
+    }
+}
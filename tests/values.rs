@@ -0,0 +1,203 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate ipfix;
+
+    use std::borrow::Cow;
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, UNIX_EPOCH};
+    use self::ipfix::{DataType, FieldValue, InformationElement, IpfixConsumer, IpfixPrinter,
+                      Registry, Semantics, Status, ValueError};
+    use super::common::message;
+
+    // all messages in these tests come from the same exporter
+    const SESSION: u64 = 0;
+
+    // template 256: octetDeltaCount (8), sourceIPv4Address (4), sourceMacAddress (6),
+    // interfaceName (variable), flowStartMilliseconds (8), flowStartNanoseconds (8), and the
+    // unassigned element 1000 (4)
+    const TEMPLATE_SET: [u8; 36] = [0x00, 0x02, 0x00, 0x24, 0x01, 0x00, 0x00, 0x07, 0x00, 0x01,
+                                    0x00, 0x08, 0x00, 0x08, 0x00, 0x04, 0x00, 0x38, 0x00, 0x06,
+                                    0x00, 0x52, 0xFF, 0xFF, 0x00, 0x98, 0x00, 0x08, 0x00, 0x9C,
+                                    0x00, 0x08, 0x03, 0xE8, 0x00, 0x04];
+
+    const DATA_SET: [u8; 47] = [0x01, 0x00, 0x00, 0x2F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                0x05, 0xDC, 0xC0, 0x00, 0x02, 0x01, 0x00, 0x1B, 0x21, 0x01,
+                                0x02, 0x03, 0x04, 0x65, 0x74, 0x68, 0x30, 0x00, 0x00, 0x01,
+                                0x5D, 0x3E, 0xF7, 0x98, 0x7B, 0xDD, 0x12, 0xAD, 0x80, 0x80,
+                                0x00, 0x00, 0x00, 0xBF, 0x80, 0x00, 0x00];
+
+    #[test]
+    fn test_get() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&DATA_SET);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &message.sets[0].records()[0];
+
+        assert_eq!(record.get(1), Some(Ok(FieldValue::Unsigned64(1500))));
+        assert_eq!(record.get(8),
+                   Some(Ok(FieldValue::Ipv4Address(Ipv4Addr::new(192, 0, 2, 1)))));
+        assert_eq!(record.get(56),
+                   Some(Ok(FieldValue::MacAddress([0x00, 0x1B, 0x21, 0x01, 0x02, 0x03]))));
+        assert_eq!(record.get(82), Some(Ok(FieldValue::String(Cow::Borrowed("eth0")))));
+        assert_eq!(record.get(152),
+                   Some(Ok(FieldValue::DateTimeMilliseconds(UNIX_EPOCH +
+                                                            Duration::from_millis(1500000000123)))));
+        assert_eq!(record.get(156),
+                   Some(Ok(FieldValue::DateTimeNanoseconds(UNIX_EPOCH +
+                                                           Duration::new(1500000000, 500000000)))));
+        assert_eq!(record.get(1000),
+                   Some(Ok(FieldValue::OctetArray(&[0xBF, 0x80, 0x00, 0x00]))));
+        assert_eq!(record.get(2), None);
+    }
+
+    #[test]
+    fn test_get_with_registry() {
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&TEMPLATE_SET)).unwrap();
+        let bytes = message(&DATA_SET);
+        let message = parser.parse_message(SESSION, &bytes).unwrap();
+        let record = &message.sets[0].records()[0];
+
+        let mut registry = Registry::iana();
+        registry.insert(1000, InformationElement::new("someRatio", DataType::Float32));
+        assert_eq!(registry.get(1000).unwrap().name, "someRatio");
+        assert_eq!(record.get_with(&registry, 1000), Some(Ok(FieldValue::Float32(-1.0))));
+        assert_eq!(record.get_with(&registry, 1), Some(Ok(FieldValue::Unsigned64(1500))));
+
+        // an empty registry knows no types at all
+        assert_eq!(record.get_with(&Registry::new(), 8),
+                   Some(Ok(FieldValue::OctetArray(&[192, 0, 2, 1]))));
+    }
+
+    #[test]
+    fn test_iana_registry() {
+        let registry = Registry::iana();
+        let octets = registry.get(1).unwrap();
+        assert_eq!(octets.name, "octetDeltaCount");
+        assert_eq!(octets.data_type, DataType::Unsigned64);
+        assert_eq!(octets.semantics, Semantics::DeltaCounter);
+        assert_eq!(octets.units.as_deref(), Some("octets"));
+        assert_eq!(registry.get(82).unwrap().data_type, DataType::String);
+        assert_eq!(registry.get(291).unwrap().data_type, DataType::BasicList);
+        assert_eq!(registry.get(34).unwrap().status, Status::Deprecated);
+        // reserved for NetFlow v9 compatibility
+        assert_eq!(registry.get(65), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(FieldValue::decode(DataType::Signed16, &[0xFF, 0xFE]),
+                   Ok(FieldValue::Signed16(-2)));
+        assert_eq!(FieldValue::decode(DataType::Boolean, &[1]),
+                   Ok(FieldValue::Boolean(true)));
+        assert_eq!(FieldValue::decode(DataType::Boolean, &[2]),
+                   Ok(FieldValue::Boolean(false)));
+        assert_eq!(FieldValue::decode(DataType::Boolean, &[0]),
+                   Err(ValueError::InvalidBoolean(0)));
+        assert_eq!(FieldValue::decode(DataType::DateTimeSeconds, &[0x00, 0x00, 0x00, 0x3C]),
+                   Ok(FieldValue::DateTimeSeconds(UNIX_EPOCH + Duration::from_secs(60))));
+        assert_eq!(FieldValue::decode(DataType::Ipv4Address, &[10, 0, 0]),
+                   Err(ValueError::InvalidLength {
+                       data_type: DataType::Ipv4Address,
+                       length: 3,
+                   }));
+        assert_eq!(FieldValue::decode(DataType::String, &[0x66, 0xFF]),
+                   Ok(FieldValue::String(Cow::Owned("f\u{FFFD}".into()))));
+    }
+
+    #[test]
+    fn test_reduced_size_encoding() {
+        assert_eq!(FieldValue::decode(DataType::Unsigned64, &[0x01, 0x00, 0x00]),
+                   Ok(FieldValue::Unsigned64(65536)));
+        assert_eq!(FieldValue::decode(DataType::Unsigned64,
+                                      &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00]),
+                   Ok(FieldValue::Unsigned64(1 << 40)));
+        assert_eq!(FieldValue::decode(DataType::Unsigned32, &[0x2A]),
+                   Ok(FieldValue::Unsigned32(42)));
+        assert_eq!(FieldValue::decode(DataType::Signed32, &[0xFF, 0x85]),
+                   Ok(FieldValue::Signed32(-123)));
+        assert_eq!(FieldValue::decode(DataType::Signed64, &[0x00, 0x85]),
+                   Ok(FieldValue::Signed64(133)));
+        assert_eq!(FieldValue::decode(DataType::Float64, &[0xBF, 0x80, 0x00, 0x00]),
+                   Ok(FieldValue::Float64(-1.0)));
+
+        let too_long = [0u8; 9];
+        assert_eq!(FieldValue::decode(DataType::Unsigned64, &too_long),
+                   Err(ValueError::InvalidLength {
+                       data_type: DataType::Unsigned64,
+                       length: 9,
+                   }));
+        assert!(FieldValue::decode(DataType::Unsigned16, &too_long[..3]).is_err());
+        assert!(FieldValue::decode(DataType::Unsigned8, &[]).is_err());
+        assert!(FieldValue::decode(DataType::Float64, &too_long[..6]).is_err());
+        assert!(FieldValue::decode(DataType::Float32, &too_long[..2]).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        fn encode(value: FieldValue, length: u16) -> Result<Vec<u8>, ValueError> {
+            let mut out = Vec::new();
+            value.encode(length, &mut out).map(|_| out)
+        }
+
+        assert_eq!(encode(FieldValue::Unsigned64(65536), 3), Ok(vec![0x01, 0x00, 0x00]));
+        assert_eq!(encode(FieldValue::Signed32(-123), 2), Ok(vec![0xFF, 0x85]));
+        assert_eq!(encode(FieldValue::Signed64(133), 2), Ok(vec![0x00, 0x85]));
+        assert_eq!(encode(FieldValue::Unsigned16(256), 1),
+                   Err(ValueError::OutOfRange {
+                       data_type: DataType::Unsigned16,
+                       length: 1,
+                   }));
+        assert!(encode(FieldValue::Signed16(128), 1).is_err());
+        assert!(encode(FieldValue::Unsigned8(1), 2).is_err());
+        assert_eq!(encode(FieldValue::Float64(-1.0), 4), Ok(vec![0xBF, 0x80, 0x00, 0x00]));
+        assert_eq!(encode(FieldValue::Boolean(false), 1), Ok(vec![2]));
+        assert_eq!(encode(FieldValue::Ipv4Address(Ipv4Addr::new(10, 0, 0, 1)), 16),
+                   Err(ValueError::InvalidLength {
+                       data_type: DataType::Ipv4Address,
+                       length: 16,
+                   }));
+        assert_eq!(encode(FieldValue::String(Cow::Borrowed("eth0")), 65535),
+                   Ok(b"\x04eth0".to_vec()));
+        assert_eq!(encode(FieldValue::OctetArray(&[0; 300]), 65535).unwrap()[..3],
+                   [0xFF, 0x01, 0x2C]);
+        assert!(encode(FieldValue::String(Cow::Borrowed("eth0")), 3).is_err());
+        assert!(encode(FieldValue::DateTimeSeconds(UNIX_EPOCH - Duration::from_secs(1)), 4)
+            .is_err());
+
+        // values come back the way they were encoded
+        let values = [FieldValue::Signed8(-1),
+                      FieldValue::DateTimeSeconds(UNIX_EPOCH + Duration::from_secs(60)),
+                      FieldValue::DateTimeMilliseconds(UNIX_EPOCH + Duration::from_millis(1)),
+                      FieldValue::DateTimeMicroseconds(UNIX_EPOCH + Duration::new(1, 999999000)),
+                      FieldValue::DateTimeNanoseconds(UNIX_EPOCH + Duration::new(1, 999999999)),
+                      FieldValue::DateTimeNanoseconds(UNIX_EPOCH - Duration::new(1, 1))];
+        for value in values.iter() {
+            let data = encode(value.clone(), 8).or_else(|_| encode(value.clone(), 4))
+                .or_else(|_| encode(value.clone(), 1))
+                .unwrap();
+            assert_eq!(FieldValue::decode(value.data_type(), &data).as_ref(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn test_reduced_size_printing() {
+        // template 256: octetDeltaCount (6), packetDeltaCount (3)
+        let template = [0x00, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x06,
+                        0x00, 0x02, 0x00, 0x03];
+        let data = [0x01, 0x00, 0x00, 0x0D, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                    0x00];
+
+        let mut parser = IpfixConsumer::new();
+        parser.parse_message(SESSION, &message(&template)).unwrap();
+        let bytes = message(&data);
+        let mut message = parser.parse_message(SESSION, &bytes).unwrap();
+        assert_eq!(message.sets[0].records()[0].get(1),
+                   Some(Ok(FieldValue::Unsigned64(1 << 32))));
+        assert_eq!(IpfixPrinter::new().print_json(message.sets.remove(0)),
+                   vec![r#"{"octetDeltaCount":4294967296,"packetDeltaCount":256}"#]);
+    }
+}